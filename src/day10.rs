@@ -1,11 +1,73 @@
-use std::fmt;
+use std::{fmt, collections::HashMap};
 
 #[cfg(test)]
 use assert_str::assert_str_trim_eq;
 
-#[derive (Clone)]
-pub enum Instruction { Noop, AddX(i32) }
+// One of the 26 registers, named from a to z
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Register(u8);
 
+impl Register {
+    pub const X: Register = Register(b'x' - b'a');
+
+    pub fn named(name: char) -> Option<Register> {
+        name.is_ascii_lowercase().then(|| Register(name as u8 - b'a'))
+    }
+
+    pub fn name(&self) -> char {
+        (b'a' + self.0) as char
+    }
+}
+
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand { Value(i64), Register(Register) }
+
+// Declares the instructions along with the number of cycles each one takes
+macro_rules! instructions {
+    ($($name:ident $(($($field:ty),*))? => $cycles:literal),*) => {
+        #[derive (Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum Instruction {
+            $($name $(($($field),*))?),*
+        }
+
+        impl Instruction {
+            pub fn cycles(&self) -> u64 {
+                match self {
+                    $(Instruction::$name { .. } => $cycles),*
+                }
+            }
+        }
+    }
+}
+
+instructions! {
+    Noop => 1,
+    Set(Register, Operand) => 1,
+    Add(Register, Operand) => 2,
+    Mul(Register, Operand) => 4,
+    Jump(usize) => 1,
+    JumpIfZero(Register, usize) => 2,
+    JumpIfNotZero(Register, usize) => 2
+}
+
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub enum AssembleError {
+    Parse(String),
+    UnknownLabel(String),
+    DuplicateLabel(String)
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssembleError::Parse(e) => write!(f, "syntax error: {e}"),
+            AssembleError::UnknownLabel(l) => write!(f, "unknown label {l}"),
+            AssembleError::DuplicateLabel(l) => write!(f, "label {l} defined twice")
+        }
+    }
+}
+
+impl std::error::Error for AssembleError {}
 
 mod parser {
     use nom::{
@@ -19,111 +81,681 @@ mod parser {
 
     use super::*;
 
-    pub fn parse(input: &str) -> IResult<&str, Vec<Instruction>> {
-        let noop = value(Instruction::Noop, tag("noop"));
+    #[derive (Clone)]
+    pub enum Condition { Always, Zero(Register), NotZero(Register) }
+
+    // Jumps still refer to labels by name until they are resolved
+    #[derive (Clone)]
+    pub enum Source {
+        Label(String),
+        Instruction(Instruction),
+        Jump(Condition, String)
+    }
+
+    fn register(input: &str) -> IResult<&str, Register> {
+        map_opt(satisfy(|c| c.is_ascii_lowercase()), Register::named)(input)
+    }
+
+    fn operand(input: &str) -> IResult<&str, Operand> {
+        alt((
+            map(i64, Operand::Value),
+            map(register, Operand::Register)))(input)
+    }
+
+    fn label(input: &str) -> IResult<&str, String> {
+        map(
+            recognize(pair(alpha1, many0(alt((alphanumeric1, tag("_")))))),
+            String::from)(input)
+    }
+
+    fn instruction(input: &str) -> IResult<&str, Source> {
+        let binary = |name, op: fn(Register, Operand) -> Instruction|
+            map(
+                tuple((tag(name), space1, register, space1, operand)),
+                move |(_,_,r,_,o)| Source::Instruction(op(r, o)));
+        let jump = |name, condition: fn(Register) -> Condition|
+            map(
+                tuple((tag(name), space1, register, space1, label)),
+                move |(_,_,r,_,l)| Source::Jump(condition(r), l));
+
+        let noop = value(Source::Instruction(Instruction::Noop), tag("noop"));
         let addx = map(
-            tuple((tag("addx"), space1, i32)),
-            |(_,_,i)| Instruction::AddX(i));
-        let instruction =  alt((noop, addx));
-        let instructions = separated_list1(line_ending, instruction);
-        all_consuming(terminated(instructions, multispace0))(input)
+            tuple((tag("addx"), space1, i64)),
+            |(_,_,i)| Source::Instruction(
+                Instruction::Add(Register::X, Operand::Value(i))));
+        let jmp = map(
+            preceded(pair(tag("jmp"), space1), label),
+            |l| Source::Jump(Condition::Always, l));
+
+        alt((
+            noop, addx,
+            binary("set", Instruction::Set),
+            binary("add", Instruction::Add),
+            binary("mul", Instruction::Mul),
+            jmp,
+            jump("jz", Condition::Zero),
+            jump("jnz", Condition::NotZero)))(input)
+    }
+
+    pub fn parse(input: &str) -> IResult<&str, Vec<Source>> {
+        let label_definition = map(terminated(label, char(':')), Source::Label);
+        let line = alt((instruction, label_definition));
+        let lines = preceded(multispace0, separated_list1(multispace1, line));
+        all_consuming(terminated(lines, multispace0))(input)
     }
 }
 
-struct Machine {
+pub fn assemble(input: &str) -> Result<Vec<Instruction>, AssembleError> {
+    let (_,source) = parser::parse(input)
+        .map_err(|e| AssembleError::Parse(e.to_string()))?;
+
+    let mut labels = HashMap::new();
+    let mut address = 0;
+    for line in &source {
+        match line {
+            parser::Source::Label(l) => {
+                if labels.insert(l.clone(), address).is_some() {
+                    return Err(AssembleError::DuplicateLabel(l.clone()));
+                }
+            },
+            _ => address += 1
+        }
+    }
+
+    let mut program = Vec::new();
+    for line in source {
+        let instruction = match line {
+            parser::Source::Label(_) => continue,
+            parser::Source::Instruction(i) => i,
+            parser::Source::Jump(condition, l) => {
+                let target = *labels.get(&l)
+                    .ok_or(AssembleError::UnknownLabel(l))?;
+                match condition {
+                    parser::Condition::Always => Instruction::Jump(target),
+                    parser::Condition::Zero(r) => Instruction::JumpIfZero(r, target),
+                    parser::Condition::NotZero(r) => Instruction::JumpIfNotZero(r, target)
+                }
+            }
+        };
+        program.push(instruction);
+    }
+    Ok(program)
+}
+
+// Cycles at which the signal strength is sampled: first, then every period
+// cycles, or only once when the period is 0
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Schedule {
+    pub first: u64,
+    pub period: u64
+}
+
+impl Schedule {
+    pub fn samples(&self, cycle: u64) -> bool {
+        match cycle.checked_sub(self.first) {
+            None => false,
+            Some(d) if self.period == 0 => d == 0,
+            Some(d) => d % self.period == 0
+        }
+    }
+}
+
+// Layout of the display, the sprite drawn on it and the signal sampling
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crt {
+    pub width: usize,
+    pub height: usize,
+    pub sprite_width: usize,
+    pub sampling: Schedule
+}
+
+impl Default for Crt {
+    fn default() -> Crt {
+        Crt {
+            width: 40,
+            height: 6,
+            sprite_width: 3,
+            sampling: Schedule { first: 20, period: 40 }
+        }
+    }
+}
+
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    width: usize,
+    height: usize,
+    pixels: Vec<bool>
+}
+
+impl Frame {
+    pub fn new(width: usize, height: usize) -> Frame {
+        Frame { width, height, pixels: vec![false; width * height] }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<bool> {
+        (x < self.width && y < self.height).then(|| self.pixels[y * self.width + x])
+    }
+
+    fn set(&mut self, x: usize, y: usize, lit: bool) {
+        self.pixels[y * self.width + x] = lit;
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in self.pixels.chunks(self.width.max(1)) {
+            for &pixel in line {
+                write!(f, "{}", if pixel {'#'} else {'.'})?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct Machine {
+    program: Vec<Instruction>,
+    pc: usize,
+    // Instruction being executed, with the number of cycles it still needs
+    current: Option<(Instruction, u64)>,
     cycle: u64,
-    x: i32,
-    screen: [[bool; 40]; 6],
-    solution1: i64
+    registers: [i64; 26],
+    crt: Crt,
+    screen: Frame,
+    // The screen as it was at each cycle, once recording is enabled
+    history: Option<Vec<Frame>>,
+    signal_strength: i64
 }
 
 impl Machine {
-    fn new() -> Machine {
+    pub fn new(program: Vec<Instruction>) -> Machine {
+        Machine::with_crt(program, Crt::default())
+    }
+
+    pub fn with_crt(program: Vec<Instruction>, crt: Crt) -> Machine {
         let mut m = Machine {
+            program,
+            pc: 0,
+            current: None,
             cycle: 1,
-            x: 1,
-            screen: [[false; 40]; 6], solution1: 0 
+            registers: [0; 26],
+            crt,
+            screen: Frame::new(crt.width, crt.height),
+            history: None,
+            signal_strength: 0
         };
+        m.registers[Register::X.0 as usize] = 1;
+        m.sample();
         m.crt_draw();
         m
     }
 
+    // Keeps a copy of the screen at every cycle from now on, starting with
+    // the current one
+    pub fn record_frames(&mut self) {
+        if self.history.is_none() {
+            self.history = Some(vec![self.screen.clone()]);
+        }
+    }
+
+    pub fn frames(&self) -> &[Frame] {
+        self.history.as_deref().unwrap_or(&[])
+    }
+
+    pub fn screen(&self) -> &Frame {
+        &self.screen
+    }
+
+    pub fn signal_strength(&self) -> i64 {
+        self.signal_strength
+    }
+
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn register(&self, r: Register) -> i64 {
+        self.registers[r.0 as usize]
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.current.is_none() && self.pc >= self.program.len()
+    }
+
     fn crt_draw(&mut self) {
-        let x = ((self.cycle - 1) % 40) as usize;
-        let y = ((self.cycle - 1) / 40) as usize;
-        if y < 6 {
-            self.screen[y][x] = (self.x - x as i32).abs() <= 1;
+        let Crt { width, height, sprite_width, .. } = self.crt;
+        if width == 0 {
+            return;
+        }
+        let x = ((self.cycle - 1) % width as u64) as usize;
+        let y = ((self.cycle - 1) / width as u64) as usize;
+        if y < height {
+            // The sprite spans sprite_width pixels centered on x, wherever x is
+            let left = self.register(Register::X) as i128 - (sprite_width as i128 - 1) / 2;
+            let lit = (left..left + sprite_width as i128).contains(&(x as i128));
+            self.screen.set(x, y, lit);
+        }
+    }
+
+    // Like the registers, the signal strength wraps around on overflow
+    fn sample(&mut self) {
+        if self.crt.sampling.samples(self.cycle) {
+            let strength = (self.cycle as i64).wrapping_mul(self.register(Register::X));
+            self.signal_strength = self.signal_strength.wrapping_add(strength);
         }
     }
 
     fn tick(&mut self) {
         self.cycle += 1;
-
-        if self.cycle % 40 == 20 {
-            let signal_strength = self.cycle as i64 * self.x as i64;
-            self.solution1 += signal_strength;
+        self.sample();
+        self.crt_draw();
+        if let Some(history) = &mut self.history {
+            history.push(self.screen.clone());
         }
+    }
 
-        self.crt_draw();
+    fn eval(&self, o: Operand) -> i64 {
+        match o {
+            Operand::Value(v) => v,
+            Operand::Register(r) => self.register(r)
+        }
     }
 
-    fn noop(&mut self) {
-        self.tick()
+    // Effects of an instruction, which take place at the end of its last
+    // cycle. Registers hold 64 bit integers that wrap around on overflow.
+    fn execute(&mut self, i: Instruction) {
+        match i {
+            Instruction::Noop => (),
+            Instruction::Set(r, o) =>
+                self.registers[r.0 as usize] = self.eval(o),
+            Instruction::Add(r, o) =>
+                self.registers[r.0 as usize] = self.register(r).wrapping_add(self.eval(o)),
+            Instruction::Mul(r, o) =>
+                self.registers[r.0 as usize] = self.register(r).wrapping_mul(self.eval(o)),
+            Instruction::Jump(target) =>
+                self.pc = target,
+            Instruction::JumpIfZero(r, target) =>
+                if self.register(r) == 0 { self.pc = target },
+            Instruction::JumpIfNotZero(r, target) =>
+                if self.register(r) != 0 { self.pc = target }
+        }
     }
 
-    fn add_x(&mut self, c: i32) {
-        self.tick();
-        self.x += c;
+    // Runs until the end of the current cycle. Returns false when the program
+    // has already ended.
+    pub fn step_cycle(&mut self) -> bool {
+        let (i, cycles_left) = match self.current {
+            Some(current) => current,
+            None => {
+                let i = *match self.program.get(self.pc) {
+                    Some(i) => i,
+                    None => return false
+                };
+                self.pc += 1;
+                (i, i.cycles())
+            }
+        };
+
+        if cycles_left <= 1 {
+            self.current = None;
+            self.execute(i);
+        }
+        else {
+            self.current = Some((i, cycles_left - 1));
+        }
         self.tick();
+        true
     }
 
-    fn execute(&mut self, i: Instruction) {
-        match i {
-            Instruction::Noop => self.noop(),
-            Instruction::AddX(c) => self.add_x(c)
+    // Runs until the end of the current instruction
+    pub fn step(&mut self) -> bool {
+        if !self.step_cycle() {
+            return false;
         }
+        while self.current.is_some() {
+            self.step_cycle();
+        }
+        true
+    }
+
+    pub fn run(&mut self) {
+        while self.step_cycle() {}
+    }
+
+    // The registers holding something, and x
+    pub fn dump(&self) -> String {
+        let mut dump = format!("cycle={} pc={}", self.cycle, self.pc);
+        for (i,&v) in self.registers.iter().enumerate() {
+            let r = Register(i as u8);
+            if v != 0 || r == Register::X {
+                dump += &format!(" {}={v}", r.name());
+            }
+        }
+        dump
     }
 }
 
 impl fmt::Display for Machine {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for line in self.screen {
-            for pixel in line {
-                write!(f, "{}", if pixel {'#'} else {'.'})?;
+        self.screen.fmt(f)
+    }
+}
+
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison { Eq, Ne, Lt, Le, Gt, Ge }
+
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint {
+    // When this cycle starts
+    Cycle(u64),
+    // When the instruction at this address is about to start
+    Address(usize),
+    // When the register starts satisfying the comparison with the value
+    Register(Register, Comparison, i64)
+}
+
+impl Breakpoint {
+    fn holds(&self, m: &Machine) -> bool {
+        match *self {
+            Breakpoint::Cycle(c) => m.cycle == c,
+            Breakpoint::Address(a) => m.current.is_none() && m.pc == a,
+            Breakpoint::Register(r, c, v) => {
+                let x = m.register(r);
+                match c {
+                    Comparison::Eq => x == v,
+                    Comparison::Ne => x != v,
+                    Comparison::Lt => x < v,
+                    Comparison::Le => x <= v,
+                    Comparison::Gt => x > v,
+                    Comparison::Ge => x >= v
+                }
             }
-            writeln!(f)?;
         }
-        Ok(())
     }
 }
 
-pub fn solve(input: &str) -> (i64,String) {
-    let (_,instructions) = parser::parse(input).unwrap();
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop { Breakpoint(usize), Halted }
+
+pub struct Debugger {
+    pub machine: Machine,
+    breakpoints: Vec<Option<Breakpoint>>
+}
+
+impl Debugger {
+    pub fn new(machine: Machine) -> Debugger {
+        Debugger { machine, breakpoints: Vec::new() }
+    }
+
+    pub fn add_breakpoint(&mut self, b: Breakpoint) -> usize {
+        self.breakpoints.push(Some(b));
+        self.breakpoints.len() - 1
+    }
+
+    pub fn remove_breakpoint(&mut self, id: usize) {
+        if let Some(b) = self.breakpoints.get_mut(id) {
+            *b = None;
+        }
+    }
+
+    pub fn step_cycle(&mut self) -> bool {
+        self.machine.step_cycle()
+    }
+
+    pub fn step(&mut self) -> bool {
+        self.machine.step()
+    }
+
+    // Runs at least one cycle, then until a breakpoint condition becomes true
+    pub fn run(&mut self) -> Stop {
+        loop {
+            let before: Vec<bool> = self.breakpoints.iter()
+                .map(|b| b.is_some_and(|b| b.holds(&self.machine)))
+                .collect();
+            if !self.machine.step_cycle() {
+                return Stop::Halted;
+            }
+            for (id,b) in self.breakpoints.iter().enumerate() {
+                if !before[id] && b.is_some_and(|b| b.holds(&self.machine)) {
+                    return Stop::Breakpoint(id);
+                }
+            }
+        }
+    }
+
+    pub fn dump(&self) -> String {
+        self.machine.dump()
+    }
+}
+
+// Capital letters of the 4x6 font the CRT displays
+const FONT: [(char, [&str; 6]); 18] = [
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Y', ["#...", "#...", ".#.#", "..#.", "..#.", "..#."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"])
+];
 
-    let mut m = Machine::new();
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub enum OcrError {
+    BadHeight(usize),
+    UnknownGlyph { index: usize, glyph: String }
+}
 
-    for i in instructions {
-        m.execute(i);
+impl fmt::Display for OcrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OcrError::BadHeight(h) =>
+                write!(f, "the screen is {h} lines high instead of 6"),
+            OcrError::UnknownGlyph { index, glyph } =>
+                write!(f, "unknown glyph at position {index}:\n{glyph}")
+        }
     }
+}
 
-    let screen = m.to_string();
-    println!("{screen}");
+impl std::error::Error for OcrError {}
 
-    (m.solution1,screen)
+// Reads a screen drawn with '#' and '.', made of letters 4 pixels wide with
+// a one pixel gap between them
+pub fn read_screen(screen: &str) -> Result<String, OcrError> {
+    let rows: Vec<&str> = screen.lines().filter(|l| !l.trim().is_empty()).collect();
+    if rows.len() != 6 {
+        return Err(OcrError::BadHeight(rows.len()));
+    }
+    let width = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+
+    let mut text = String::new();
+    for (index,x) in (0..width).step_by(5).enumerate() {
+        let glyph: Vec<&str> =
+            rows.iter().map(|r| r.get(x..x+4).unwrap_or("")).collect();
+        let letter = FONT.iter().find(|(_,g)| g[..] == glyph[..]);
+        match letter {
+            Some(&(c,_)) => text.push(c),
+            None => return Err(OcrError::UnknownGlyph {
+                index,
+                glyph: glyph.join("\n")
+            })
+        }
+    }
+    Ok(text)
+}
+
+fn run(input: &str) -> Machine {
+    let program = assemble(input).unwrap();
+    let mut m = Machine::new(program);
+    m.run();
+    m
+}
+
+// The signal strength sum with the screen as drawn
+pub fn draw(input: &str) -> (i64,String) {
+    let m = run(input);
+    (m.signal_strength(),m.to_string())
+}
+
+pub fn solve(input: &str) -> (i64,Result<String,OcrError>) {
+    let m = run(input);
+    (m.signal_strength(),read_screen(&m.to_string()))
 }
 
 #[test]
 fn test10_1() {
-    let (solution1,solution2) = solve(&include_str!("../inputs/day10.1"));
+    let (solution1,solution2) = draw(&include_str!("../inputs/day10.1"));
     let oracle2 = include_str!("../inputs/day10.1.oracle");
     assert_eq!(solution1, 13140);
     assert_str_trim_eq!(solution2, String::from(oracle2));
+
+    let (_,text) = solve(include_str!("../inputs/day10.1"));
+    assert_eq!(text, Err(OcrError::UnknownGlyph {
+        index: 0,
+        glyph: String::from("##..\n###.\n####\n####\n####\n####")
+    }));
 }
 
 #[test]
 fn test10_2() {
-    let (solution1,solution2) = solve(&include_str!("../inputs/day10.2"));
+    let (solution1,solution2) = draw(&include_str!("../inputs/day10.2"));
     let oracle2 = include_str!("../inputs/day10.2.oracle");
     assert_eq!(solution1, 13520);
     assert_str_trim_eq!(solution2, String::from(oracle2));
+
+    let solution = solve(include_str!("../inputs/day10.2"));
+    assert_eq!(solution, (13520, Ok(String::from("PGPHBEAB"))));
+}
+
+#[test]
+fn test10_crt() {
+    let program = assemble("addx 1\naddx 1\nnoop\nnoop").unwrap();
+    let crt = Crt {
+        width: 4,
+        height: 2,
+        sprite_width: 1,
+        sampling: Schedule { first: 3, period: 2 }
+    };
+    let mut m = Machine::with_crt(program.clone(), crt);
+    m.record_frames();
+    m.run();
+    assert_eq!(m.to_string(), ".##.\n....\n");
+    assert_eq!(m.signal_strength(), 3*2 + 5*3 + 7*3);
+    assert_eq!(m.frames().len(), 7);
+    assert_eq!(m.frames()[1].to_string(), ".#..\n....\n");
+    assert_eq!(m.frames()[6], *m.screen());
+    assert_eq!(m.screen().get(2, 0), Some(true));
+    assert_eq!(m.screen().get(4, 0), None);
+
+    let crt = Crt { sprite_width: 2, sampling: Schedule { first: 5, period: 0 }, ..crt };
+    let mut m = Machine::with_crt(program, crt);
+    m.run();
+    assert_eq!(m.to_string(), ".###\n....\n");
+    assert_eq!(m.signal_strength(), 5*3);
+    assert!(m.frames().is_empty());
+
+    let program = assemble("set x 9223372036854775807\nnoop").unwrap();
+    let crt = Crt { sampling: Schedule { first: 2, period: 1 }, ..crt };
+    let mut m = Machine::with_crt(program, crt);
+    m.run();
+    assert_eq!(m.signal_strength(), i64::MAX.wrapping_mul(2).wrapping_add(i64::MAX.wrapping_mul(3)));
+}
+
+#[test]
+fn test10_ocr() {
+    let mut screen = vec![String::new(); 6];
+    for (_,glyph) in FONT {
+        for (line,row) in screen.iter_mut().zip(glyph) {
+            *line += row;
+            *line += ".";
+        }
+    }
+    let letters: String = FONT.iter().map(|&(c,_)| c).collect();
+    assert_eq!(read_screen(&screen.join("\n")), Ok(letters));
+    assert_eq!(read_screen("####\n####\n"), Err(OcrError::BadHeight(2)));
+}
+
+#[cfg(test)]
+const FACTORIAL: &str = "\
+set a 5
+set b 1
+loop:
+    mul b a
+    add a -1
+    jnz a loop
+";
+
+#[test]
+fn test10_assembler() {
+    let program = assemble(FACTORIAL).unwrap();
+    let (a, b) = (Register::named('a').unwrap(), Register::named('b').unwrap());
+    assert_eq!(program[2], Instruction::Mul(b, Operand::Register(a)));
+    assert_eq!(program[4], Instruction::JumpIfNotZero(a, 2));
+
+    let mut m = Machine::new(program);
+    m.run();
+    assert_eq!(m.register(b), 120);
+    assert_eq!(m.cycle(), 1 + 2 + 5 * 8);
+    assert_eq!(m.dump(), "cycle=43 pc=5 b=120 x=1");
+
+    let mut m = Machine::new(assemble("set a 9223372036854775807\nadd a 1\nset b 3\nmul b -6148914691236517205").unwrap());
+    m.run();
+    assert_eq!(m.register(a), i64::MIN);
+    assert_eq!(m.register(b), 1);
+
+    assert_eq!(assemble("jmp nowhere"),
+        Err(AssembleError::UnknownLabel(String::from("nowhere"))));
+    assert_eq!(assemble("l:\nnoop\nl:\n"),
+        Err(AssembleError::DuplicateLabel(String::from("l"))));
+}
+
+#[test]
+fn test10_debugger() {
+    let b = Register::named('b').unwrap();
+    let mut debugger = Debugger::new(Machine::new(assemble(FACTORIAL).unwrap()));
+
+    assert!(debugger.step());
+    assert_eq!(debugger.dump(), "cycle=2 pc=1 a=5 x=1");
+
+    let at_10 = debugger.add_breakpoint(Breakpoint::Cycle(10));
+    let big_b = debugger.add_breakpoint(Breakpoint::Register(b, Comparison::Gt, 50));
+    assert_eq!(debugger.run(), Stop::Breakpoint(at_10));
+    assert_eq!(debugger.machine.cycle(), 10);
+    assert_eq!(debugger.run(), Stop::Breakpoint(big_b));
+    assert_eq!(debugger.dump(), "cycle=23 pc=3 a=3 b=60 x=1");
+
+    debugger.remove_breakpoint(big_b);
+    let loop_start = debugger.add_breakpoint(Breakpoint::Address(2));
+    assert_eq!(debugger.run(), Stop::Breakpoint(loop_start));
+    assert_eq!(debugger.machine.register(b), 60);
+    assert!(debugger.step_cycle());
+    assert_eq!(debugger.machine.pc(), 3);
+    assert_eq!(debugger.machine.register(b), 60);
+
+    debugger.remove_breakpoint(loop_start);
+    assert_eq!(debugger.run(), Stop::Halted);
+    assert!(debugger.machine.is_halted());
+    assert_eq!(debugger.machine.register(b), 120);
 }
@@ -1,30 +1,74 @@
-use std::collections::BinaryHeap;
+use std::{fmt, io, collections::BinaryHeap};
 
-#[derive (Debug,Clone)]
-pub enum Operand {
+use num_bigint::{BigInt, BigUint};
+use num_integer::Integer;
+use num_traits::ToPrimitive;
+use serde_json::{json, Value};
+
+// Arithmetic expression computing the new worry level from the old one
+#[derive (Debug,Clone,PartialEq,Eq)]
+pub enum Expr {
     Old,
-    Value(u32)
+    Value(i64),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>)
 }
 
-#[derive (Debug,Clone)]
-pub enum Op {
-    Mul(Operand, Operand),
-    Add(Operand, Operand)
+impl Expr {
+    // Whether the expression can be computed modulo a multiple of the test
+    // divisors, which rules out division
+    pub fn is_reducible(&self) -> bool {
+        match self {
+            Expr::Old | Expr::Value(_) => true,
+            Expr::Add(l, r) | Expr::Sub(l, r) | Expr::Mul(l, r) =>
+                l.is_reducible() && r.is_reducible(),
+            Expr::Div(..) => false
+        }
+    }
 }
 
-#[derive (Debug,Clone)]
+#[derive (Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Comparison { Lt, Eq, Gt }
+
+#[derive (Debug,Clone,PartialEq,Eq)]
 pub enum Test {
-    DivisibleBy(u32)
+    DivisibleBy(u32),
+    ModuloEquals { modulus: u32, remainder: u32 },
+    Compare(Comparison, i64),
+    // Throws to the target matching the remainder
+    Modulo(u32)
+}
+
+impl Test {
+    // The divisor the outcome depends on, if the test can be done on a
+    // worry level reduced modulo a multiple of it
+    pub fn divisor(&self) -> Option<u32> {
+        match *self {
+            Test::DivisibleBy(d) | Test::Modulo(d) |
+            Test::ModuloEquals { modulus: d, .. } => Some(d),
+            Test::Compare(..) => None
+        }
+    }
+
+    pub fn outcomes(&self) -> usize {
+        match *self {
+            Test::Modulo(d) => d as usize,
+            _ => 2
+        }
+    }
 }
 
 #[derive (Debug,Clone)]
 pub struct Monkey {
     id: usize,
     items: Vec<u64>,
-    operation: Op,
+    operation: Expr,
     test: Test,
-    throw_to_if_true: usize,
-    throw_to_if_false: usize,
+    // Target for each outcome of the test, true before false for the tests
+    // that succeed or fail
+    targets: Vec<usize>,
     inspections: u32
 }
 
@@ -53,26 +97,78 @@ mod parser {
         map(u32, |x| x as usize)(input)
     }
 
-    fn operand(input: &str) -> IResult<&str, Operand> {
-        let old = value(Operand::Old, tag("old"));
-        let val = map(u32, Operand::Value);
-        alt((old, val))(input)
+    fn factor(input: &str) -> IResult<&str, Expr> {
+        let old = value(Expr::Old, tag("old"));
+        let val = map(map_res(u64, i64::try_from), Expr::Value);
+        let parenthesized = delimited(
+            char('('), delimited(space0, expr, space0), char(')'));
+        alt((old, val, parenthesized))(input)
     }
 
-    fn op(input: &str) -> IResult<&str, Op> {
-        let (input, (_,o1,c,o2)) = tuple(
-            (tag("new = "), operand, ws(one_of("+*")), operand))(input)?;
-        let r = match c {
-            '+' => Op::Add(o1,o2),
-            '*' => Op::Mul(o1,o2),
-            _ => panic!()
-        };
-        Ok((input, r))
+    fn term(input: &str) -> IResult<&str, Expr> {
+        let (input, first) = factor(input)?;
+        fold_many0(
+            pair(delimited(space0, one_of("*/"), space0), factor),
+            move || first.clone(),
+            |l, (c, r)| match c {
+                '*' => Expr::Mul(Box::new(l), Box::new(r)),
+                _ => Expr::Div(Box::new(l), Box::new(r))
+            })(input)
     }
 
-    fn monkey(input: &str) -> IResult<&str, Monkey> {
-        let test = map(preceded(tag("divisible by "), u32), Test::DivisibleBy);
+    fn expr(input: &str) -> IResult<&str, Expr> {
+        let (input, first) = term(input)?;
+        fold_many0(
+            pair(delimited(space0, one_of("+-"), space0), term),
+            move || first.clone(),
+            |l, (c, r)| match c {
+                '+' => Expr::Add(Box::new(l), Box::new(r)),
+                _ => Expr::Sub(Box::new(l), Box::new(r))
+            })(input)
+    }
+
+    fn op(input: &str) -> IResult<&str, Expr> {
+        preceded(tag("new = "), expr)(input)
+    }
+
+    fn test(input: &str) -> IResult<&str, Test> {
+        let comparison = alt((
+            value(Comparison::Lt, tag("less than ")),
+            value(Comparison::Eq, tag("equal to ")),
+            value(Comparison::Gt, tag("greater than "))));
+
+        alt((
+            map(preceded(tag("divisible by "), u32), Test::DivisibleBy),
+            map(
+                tuple((tag("modulo "), u32, tag(" equals "), u32)),
+                |(_,modulus,_,remainder)| Test::ModuloEquals { modulus, remainder }),
+            map(preceded(tag("modulo "), u32), Test::Modulo),
+            map(pair(comparison, i64), |(c,v)| Test::Compare(c, v))))(input)
+    }
+
+    fn branches(input: &str) -> IResult<&str, Vec<usize>> {
+        let (input, if_true) = ws(preceded(
+            tag("If true: throw to monkey "),
+            usize))(input)?;
+        let (input, if_false) = ws(preceded(
+            tag("If false: throw to monkey "),
+            usize))(input)?;
+        Ok((input, vec![if_true, if_false]))
+    }
+
+    // One target for each remainder, in any order
+    fn remainders(input: &str, count: u32) -> IResult<&str, Vec<usize>> {
+        let target = ws(pair(
+            delimited(tag("If remainder is "), u32, tag(": throw to monkey ")),
+            usize));
+        map_opt(many1(target), |mut targets| {
+            targets.sort_by_key(|&(r,_)| r);
+            let complete = targets.iter().map(|&(r,_)| r).eq(0..count);
+            complete.then(|| targets.into_iter().map(|(_,t)| t).collect())
+        })(input)
+    }
 
+    fn monkey(input: &str) -> IResult<&str, Monkey> {
         let (input,id) = ws(delimited(
             tag("Monkey "), usize, tag(":")))(input)?;
         let (input, items) = ws(preceded(
@@ -80,16 +176,13 @@ mod parser {
             separated_list1(tag(", "), u64)))(input)?;
         let (input, operation) = ws(preceded(tag("Operation: "), op))(input)?;
         let (input, test) = ws(preceded(tag("Test: "), test))(input)?;
-        let (input, throw_to_if_true) = ws(preceded(
-            tag("If true: throw to monkey "),
-            usize))(input)?;
-        let (input, throw_to_if_false) = ws(preceded(
-            tag("If false: throw to monkey "),
-            usize))(input)?;
+        let (input, targets) = match test {
+            Test::Modulo(d) => remainders(input, d)?,
+            _ => branches(input)?
+        };
     
         let m = Monkey {
-            id, items, operation, test,
-            throw_to_if_true, throw_to_if_false,
+            id, items, operation, test, targets,
             inspections: 0
         };
         Ok((input, m))
@@ -101,74 +194,473 @@ mod parser {
     }
 }
 
-fn apply(op: &Op, old_w: u64) -> u64 {
-    let eval = |x: &Operand| {
-        match *x {
-            Operand::Old => old_w,
-            Operand::Value(x) => x as u64
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub enum MonkeyError {
+    // Throws refer to monkeys by number, so they must be listed in order
+    Misnumbered { position: usize, id: usize },
+    ZeroDivisor { monkey: usize },
+    NoSuchMonkey { monkey: usize, target: usize },
+    TooFewMonkeys,
+    DivisionByZero { monkey: usize },
+    // The monkey's operation or test does not survive modular reduction
+    NotReducible { monkey: usize }
+}
+
+impl fmt::Display for MonkeyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MonkeyError::Misnumbered { position, id } =>
+                write!(f, "monkey {id} is listed in position {position}"),
+            MonkeyError::ZeroDivisor { monkey } =>
+                write!(f, "monkey {monkey} tests divisibility by 0"),
+            MonkeyError::NoSuchMonkey { monkey, target } =>
+                write!(f, "monkey {monkey} throws to missing monkey {target}"),
+            MonkeyError::TooFewMonkeys =>
+                write!(f, "monkey business needs at least two monkeys"),
+            MonkeyError::DivisionByZero { monkey } =>
+                write!(f, "monkey {monkey} divided a worry level by 0"),
+            MonkeyError::NotReducible { monkey } =>
+                write!(f, "monkey {monkey} needs worry levels that are not reduced")
+        }
+    }
+}
+
+impl std::error::Error for MonkeyError {}
+
+// Every divisor must be usable and every throw must land on a monkey
+pub fn validate(monkeys: &[Monkey]) -> Result<(), MonkeyError> {
+    if monkeys.len() < 2 {
+        return Err(MonkeyError::TooFewMonkeys);
+    }
+    for (i,m) in monkeys.iter().enumerate() {
+        if m.id != i {
+            return Err(MonkeyError::Misnumbered { position: i, id: m.id });
+        }
+        if m.test.divisor() == Some(0) {
+            return Err(MonkeyError::ZeroDivisor { monkey: i });
+        }
+        if let Some(&target) = m.targets.iter().find(|&&t| t >= monkeys.len()) {
+            return Err(MonkeyError::NoSuchMonkey { monkey: i, target });
         }
+    }
+    Ok(())
+}
+
+// The smallest modulus preserving the outcome of every test, which is the
+// lcm of the divisors
+pub fn worry_modulus(monkeys: &[Monkey]) -> BigUint {
+    monkeys.iter()
+        .filter_map(|m| m.test.divisor())
+        .fold(BigUint::from(1u32), |l, d| l.lcm(&BigUint::from(d)))
+}
+
+// Integer types that worry levels can be computed with
+trait Worry: Integer + Clone + From<i64> + From<u64> + ToPrimitive + Into<BigInt> {}
+
+impl<W: Integer + Clone + From<i64> + From<u64> + ToPrimitive + Into<BigInt>> Worry for W {}
+
+// How worry levels are kept manageable after each inspection
+enum Relief<W> {
+    DivideBy(W),
+    // Every operation is done modulo this
+    Modulo(W)
+}
+
+fn eval<W: Worry>(e: &Expr, old: &W, relief: &Relief<W>) -> Option<W> {
+    let reduce = |w: W| match relief {
+        Relief::Modulo(m) => w.mod_floor(m),
+        _ => w
     };
-    match op {
-        Op::Mul(l, r) => eval(l) * eval(r),
-        Op::Add(l, r) => eval(l) + eval(r)
+    let binary = |l, r, f: fn(W, W) -> W| {
+        Some(reduce(f(eval(l, old, relief)?, eval(r, old, relief)?)))
+    };
+    match e {
+        Expr::Old => Some(old.clone()),
+        Expr::Value(v) => Some(reduce(W::from(*v))),
+        Expr::Add(l, r) => binary(l, r, |a, b| a + b),
+        Expr::Sub(l, r) => binary(l, r, |a, b| a - b),
+        Expr::Mul(l, r) => binary(l, r, |a, b| a * b),
+        Expr::Div(l, r) => {
+            let d = eval(r, old, relief)?;
+            if d.is_zero() {
+                return None;
+            }
+            Some(reduce(eval(l, old, relief)?.div_floor(&d)))
+        }
     }
 }
 
-fn test(t: &Test, x: u64) -> bool {
-    match t {
-        &Test::DivisibleBy(y) => x % (y as u64) == 0
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Passed(bool),
+    Remainder(usize)
+}
+
+impl Outcome {
+    // Index of the matching target
+    fn index(&self) -> usize {
+        match *self {
+            Outcome::Passed(b) => if b {0} else {1},
+            Outcome::Remainder(r) => r
+        }
+    }
+}
+
+fn outcome<W: Worry>(t: &Test, x: &W) -> Outcome {
+    match *t {
+        Test::DivisibleBy(d) => Outcome::Passed(x.is_multiple_of(&W::from(d as i64))),
+        Test::ModuloEquals { modulus, remainder } => Outcome::Passed(
+            x.mod_floor(&W::from(modulus as i64)) == W::from(remainder as i64)),
+        Test::Compare(c, v) => Outcome::Passed(match c {
+            Comparison::Lt => *x < W::from(v),
+            Comparison::Eq => *x == W::from(v),
+            Comparison::Gt => *x > W::from(v)
+        }),
+        Test::Modulo(d) => Outcome::Remainder(
+            x.mod_floor(&W::from(d as i64)).to_usize().unwrap_or(0))
     }
 }
 
-pub fn solve_part(mut monkeys: Vec<Monkey>, worry_decreases: bool, rounds: u32) -> Option<u64> {
-    for _i in 1..=rounds {
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub enum EventKind {
+    // The worry level before the inspection
+    Inspect { worry: BigInt },
+    // The worry level once the operation and relief are applied
+    Worry { worry: BigInt },
+    Test { outcome: Outcome },
+    Throw { target: usize }
+}
+
+// Something that happened to an item while a monkey held it. Items are
+// numbered in the order they are listed, starting with monkey 0's.
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub round: u32,
+    pub monkey: usize,
+    pub item: usize,
+    // The monkey holding the item at the start
+    pub origin: usize,
+    pub kind: EventKind
+}
+
+impl Event {
+    pub fn to_json(&self) -> Value {
+        // Levels too large for a JSON number are written as strings
+        let worry = |w: &BigInt| match w.to_i64() {
+            Some(w) => json!(w),
+            None => json!(w.to_string())
+        };
+        let mut value = json!({
+            "round": self.round,
+            "monkey": self.monkey,
+            "item": self.item,
+            "origin": self.origin
+        });
+        let (event, field, data) = match &self.kind {
+            EventKind::Inspect { worry: w } => ("inspect", "worry", worry(w)),
+            EventKind::Worry { worry: w } => ("worry", "worry", worry(w)),
+            EventKind::Test { outcome: Outcome::Passed(b) } => ("test", "passed", json!(b)),
+            EventKind::Test { outcome: Outcome::Remainder(r) } => ("test", "remainder", json!(r)),
+            EventKind::Throw { target } => ("throw", "target", json!(target))
+        };
+        value["event"] = json!(event);
+        value[field] = data;
+        value
+    }
+}
+
+// Where each item started and every monkey it was thrown to since
+pub struct Provenance {
+    origins: Vec<usize>,
+    // Round and receiving monkey of each throw, for every item
+    throws: Vec<Vec<(u32, usize)>>
+}
+
+impl Provenance {
+    pub fn new(monkeys: &[Monkey]) -> Provenance {
+        let origins: Vec<usize> = monkeys.iter().enumerate()
+            .flat_map(|(i,m)| m.items.iter().map(move |_| i))
+            .collect();
+        let throws = vec![Vec::new(); origins.len()];
+        Provenance { origins, throws }
+    }
+
+    pub fn record(&mut self, e: &Event) {
+        if let EventKind::Throw { target } = e.kind {
+            self.throws[e.item].push((e.round, target));
+        }
+    }
+
+    pub fn items(&self) -> usize {
+        self.origins.len()
+    }
+
+    pub fn origin(&self, item: usize) -> Option<usize> {
+        self.origins.get(item).copied()
+    }
+
+    // The monkey holding the item at the end of a round, round 0 being the
+    // start
+    pub fn holder(&self, item: usize, round: u32) -> Option<usize> {
+        let throws = self.throws.get(item)?;
+        let seen = throws.partition_point(|&(r,_)| r <= round);
+        match seen {
+            0 => self.origin(item),
+            n => Some(throws[n-1].1)
+        }
+    }
+}
+
+// Events are only built when there is someone to report them to
+fn simulate<W, F>(
+        monkeys: &mut [Monkey],
+        rounds: u32,
+        relief: Relief<W>,
+        mut on_event: Option<&mut F>) -> Result<(), MonkeyError>
+    where W: Worry, F: FnMut(&Event)
+{
+    // Items are tracked with their number and starting monkey
+    let mut count = 0;
+    let mut items: Vec<Vec<(usize, usize, W)>> = monkeys.iter().enumerate()
+        .map(|(i,m)| m.items.iter()
+            .map(|&w| {
+                count += 1;
+                (count - 1, i, W::from(w))
+            })
+            .collect())
+        .collect();
+
+    for round in 1..=rounds {
         for i in 0..monkeys.len() {
-            let m = monkeys.get_mut(i)?;
-            let mut items_t = Vec::new();
-            let mut items_f = Vec::new();
+            let m = &mut monkeys[i];
+            let mut thrown: Vec<Vec<_>> = vec![Vec::new(); m.targets.len()];
+
+            for (item, origin, worry) in items[i].drain(..) {
+                let mut emit = |kind: &dyn Fn() -> EventKind| if let Some(f) = on_event.as_mut() {
+                    f(&Event { round, monkey: i, item, origin, kind: kind() });
+                };
 
-            for item in &m.items {
                 m.inspections += 1;
-                let mut w = *item;
-                w = apply(&m.operation, w);
-                if worry_decreases {
-                    w /= 3;
-                }
-                else {
-                    w %= 2 * 3 * 5 * 7 * 11 * 13 * 17 * 19 * 23;
-                }
-                if test(&m.test, w) {
-                    items_t.push(w);
-                }
-                else {
-                    items_f.push(w);
+                emit(&|| EventKind::Inspect { worry: worry.clone().into() });
+                let mut w = eval(&m.operation, &worry, &relief)
+                    .ok_or(MonkeyError::DivisionByZero { monkey: i })?;
+                if let Relief::DivideBy(d) = &relief {
+                    w = w.div_floor(d);
                 }
+                emit(&|| EventKind::Worry { worry: w.clone().into() });
+
+                let outcome = outcome(&m.test, &w);
+                emit(&|| EventKind::Test { outcome });
+                emit(&|| EventKind::Throw { target: m.targets[outcome.index()] });
+                thrown[outcome.index()].push((item, origin, w));
             }
 
-            let dest_t = m.throw_to_if_true;
-            let dest_f = m.throw_to_if_false;
-            m.items.clear();
+            for (&target, mut t) in m.targets.iter().zip(thrown) {
+                items[target].append(&mut t);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_reducible(monkeys: &[Monkey]) -> Result<(), MonkeyError> {
+    let irreducible = monkeys.iter()
+        .position(|m| !m.operation.is_reducible() || m.test.divisor().is_none());
+    match irreducible {
+        Some(monkey) => Err(MonkeyError::NotReducible { monkey }),
+        None => Ok(())
+    }
+}
+
+// Plays the rounds, reporting everything that happens to the items
+pub fn run<F: FnMut(&Event)>(
+        monkeys: &mut [Monkey],
+        worry_decreases: bool,
+        rounds: u32,
+        mut on_event: F) -> Result<(), MonkeyError> {
+    play(monkeys, worry_decreases, rounds, Some(&mut on_event))
+}
+
+fn play<F: FnMut(&Event)>(
+        monkeys: &mut [Monkey],
+        worry_decreases: bool,
+        rounds: u32,
+        on_event: Option<&mut F>) -> Result<(), MonkeyError> {
+    validate(monkeys)?;
+
+    if worry_decreases {
+        return simulate(monkeys, rounds, Relief::DivideBy(BigInt::from(3)), on_event);
+    }
+
+    check_reducible(monkeys)?;
+
+    // Products of two reduced levels must fit in an i128
+    let modulus = worry_modulus(monkeys);
+    match i64::try_from(&modulus) {
+        Ok(m) => simulate(monkeys, rounds, Relief::Modulo(m as i128), on_event),
+        Err(_) => simulate(monkeys, rounds, Relief::Modulo(BigInt::from(modulus)), on_event)
+    }
+}
+
+// Writes the events as JSON lines
+pub fn write_events<O: io::Write>(
+        mut monkeys: Vec<Monkey>,
+        worry_decreases: bool,
+        rounds: u32,
+        out: &mut O) -> io::Result<()> {
+    let mut written = Ok(());
+    run(&mut monkeys, worry_decreases, rounds, |e| {
+        if written.is_ok() {
+            written = writeln!(out, "{}", e.to_json());
+        }
+    }).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    written
+}
+
+pub fn solve_part(mut monkeys: Vec<Monkey>, worry_decreases: bool, rounds: u32) -> Result<u64, MonkeyError> {
+    play::<fn(&Event)>(&mut monkeys, worry_decreases, rounds, None)?;
 
-            monkeys.get_mut(dest_t)?.items.append(&mut items_t);
-            monkeys.get_mut(dest_f)?.items.append(&mut items_f);            
+    let mut inspections = BinaryHeap::from_iter(monkeys.iter().map(|m| m.inspections));
+    let first = inspections.pop().ok_or(MonkeyError::TooFewMonkeys)?;
+    let second = inspections.pop().ok_or(MonkeyError::TooFewMonkeys)?;
+    Ok(first as u64 * second as u64)
+}
+
+// Plays a round for a single item held by a monkey, counting the
+// inspections. The item keeps moving while it is thrown to monkeys that have
+// not had their turn yet, and the new holder is returned with its worry level.
+fn item_round<W: Worry>(
+        monkeys: &[Monkey],
+        holder: usize,
+        worry: W,
+        relief: &Relief<W>,
+        inspections: &mut [u64]) -> Result<(usize, W), MonkeyError> {
+    let mut current = holder;
+    let mut w = worry;
+    loop {
+        let m = &monkeys[current];
+        inspections[current] += 1;
+        w = eval(&m.operation, &w, relief)
+            .ok_or(MonkeyError::DivisionByZero { monkey: current })?;
+        let target = m.targets[outcome(&m.test, &w).index()];
+        if target <= current {
+            return Ok((target, w));
         }
+        current = target;
+    }
+}
+
+// Plays rounds for a single item, adding up its inspections
+fn item_rounds<W: Worry>(
+        monkeys: &[Monkey],
+        state: (usize, W),
+        rounds: u64,
+        relief: &Relief<W>,
+        inspections: &mut [u64]) -> Result<(usize, W), MonkeyError> {
+    let mut state = state;
+    for _ in 0..rounds {
+        state = item_round(monkeys, state.0, state.1, relief, inspections)?;
+    }
+    Ok(state)
+}
+
+// Inspections caused by a single item. Its holder and worry level at the
+// start of a round decide everything that follows, so once they repeat the
+// rounds in between repeat forever. The cycle is found with Brent's
+// algorithm, unless playing every round is quicker.
+fn item_inspections<W: Worry>(
+        monkeys: &[Monkey],
+        holder: usize,
+        worry: W,
+        relief: &Relief<W>,
+        rounds: u64) -> Result<Vec<u64>, MonkeyError> {
+    let mut inspections = vec![0; monkeys.len()];
+    let mut ignored = vec![0; monkeys.len()];
+    let start = (holder, worry);
 
-        if _i % 1000 == 0 || _i == 20 || _i == 1 {
-            println!("== After round {_i} ==");
-            for m in &monkeys {
-                println!("Monkey {} inspected  items {} times", m.id, m.inspections);
+    let (mut power, mut period) = (1, 1);
+    let mut tortoise = start.clone();
+    let mut hare = item_rounds(monkeys, start.clone(), 1, relief, &mut ignored)?;
+    let mut played = 1;
+    while tortoise != hare {
+        if played >= rounds {
+            item_rounds(monkeys, start, rounds, relief, &mut inspections)?;
+            return Ok(inspections);
+        }
+        if power == period {
+            tortoise = hare.clone();
+            power *= 2;
+            period = 0;
+        }
+        hare = item_rounds(monkeys, hare, 1, relief, &mut ignored)?;
+        period += 1;
+        played += 1;
+    }
+
+    // The first round of the cycle
+    let mut first = 0;
+    let mut tortoise = start.clone();
+    let mut hare = item_rounds(monkeys, start.clone(), period, relief, &mut ignored)?;
+    while tortoise != hare {
+        tortoise = item_rounds(monkeys, tortoise, 1, relief, &mut ignored)?;
+        hare = item_rounds(monkeys, hare, 1, relief, &mut ignored)?;
+        first += 1;
+    }
+
+    if rounds <= first {
+        item_rounds(monkeys, start, rounds, relief, &mut inspections)?;
+        return Ok(inspections);
+    }
+    let entry = item_rounds(monkeys, start, first, relief, &mut inspections)?;
+    let mut cycle = vec![0; monkeys.len()];
+    item_rounds(monkeys, entry.clone(), period, relief, &mut cycle)?;
+    let (cycles, rest) = ((rounds - first) / period, (rounds - first) % period);
+    item_rounds(monkeys, entry, rest, relief, &mut inspections)?;
+    for (total, count) in inspections.iter_mut().zip(cycle) {
+        *total += cycles * count;
+    }
+    Ok(inspections)
+}
+
+fn fast_forward_with<W: Worry>(monkeys: &[Monkey], rounds: u64, modulus: W)
+        -> Result<Vec<u64>, MonkeyError> {
+    let relief = Relief::Modulo(modulus);
+    let mut inspections = vec![0; monkeys.len()];
+    for (i,m) in monkeys.iter().enumerate() {
+        for &w in &m.items {
+            let counts = item_inspections(monkeys, i, W::from(w), &relief, rounds)?;
+            for (total, count) in inspections.iter_mut().zip(counts) {
+                *total += count;
             }
         }
     }
+    Ok(inspections)
+}
 
-    let mut inspections = BinaryHeap::from_iter(monkeys.iter().map(|m| m.inspections));
-    Some (inspections.pop()? as u64 * inspections.pop()? as u64)
+// Inspections made by each monkey in the rounds where worry levels are
+// reduced, without playing every round when items go round in circles
+pub fn fast_forward(monkeys: &[Monkey], rounds: u64) -> Result<Vec<u64>, MonkeyError> {
+    validate(monkeys)?;
+    check_reducible(monkeys)?;
+
+    let modulus = worry_modulus(monkeys);
+    match i64::try_from(&modulus) {
+        Ok(m) => fast_forward_with(monkeys, rounds, m as i128),
+        Err(_) => fast_forward_with(monkeys, rounds, BigInt::from(modulus))
+    }
+}
+
+// The product of the two largest inspection counts
+pub fn monkey_business(inspections: &[u64]) -> Option<u128> {
+    let mut inspections = BinaryHeap::from_iter(inspections.iter().copied());
+    Some(inspections.pop()? as u128 * inspections.pop()? as u128)
 }
 
 pub fn solve(input: &str) -> Option<(u64,u64)> {
     let (_,monkeys) = parser::parse(input).unwrap();
-    let solution1 = solve_part(monkeys.clone(), true, 20)?;
-    let solution2 = solve_part(monkeys, false, 10000 )?;
+    let solution1 = solve_part(monkeys.clone(), true, 20).ok()?;
+    let inspections = fast_forward(&monkeys, 10000).ok()?;
+    let solution2 = monkey_business(&inspections)?.try_into().ok()?;
     Some((solution1, solution2))
 }
 
@@ -183,3 +675,228 @@ fn test11_2() {
     let solution = solve(&include_str!("../inputs/day11.2"));
     assert_eq!(solution, Some((117624,16792940265)));
 }
+
+#[cfg(test)]
+fn unreduced_business(mut monkeys: Vec<Monkey>, rounds: u32) -> u64 {
+    simulate(&mut monkeys, rounds, Relief::DivideBy(BigInt::from(1)), None::<&mut fn(&Event)>).unwrap();
+    let mut inspections: Vec<u32> = monkeys.iter().map(|m| m.inspections).collect();
+    inspections.sort_unstable_by(|a, b| b.cmp(a));
+    inspections[0] as u64 * inspections[1] as u64
+}
+
+#[test]
+fn test11_modulus() {
+    let (_,monkeys) = parser::parse(include_str!("../inputs/day11.1")).unwrap();
+    assert_eq!(worry_modulus(&monkeys), BigUint::from(23u32 * 19 * 13 * 17));
+    assert_eq!(solve_part(monkeys.clone(), false, 8), Ok(unreduced_business(monkeys.clone(), 8)));
+
+    let mut composite = monkeys.clone();
+    for (m,d) in composite.iter_mut().zip([4, 6, 29, 58]) {
+        m.test = Test::DivisibleBy(d);
+    }
+    assert_eq!(worry_modulus(&composite), BigUint::from(12u32 * 29));
+    assert_eq!(solve_part(composite.clone(), false, 8), Ok(unreduced_business(composite, 8)));
+
+    // Too large for u64 arithmetic
+    let mut large = monkeys.clone();
+    for (m,d) in large.iter_mut().zip([4294967291, 4294967279, 4294967231, 4294967197]) {
+        m.test = Test::DivisibleBy(d);
+    }
+    assert!(u64::try_from(&worry_modulus(&large)).is_err());
+    assert_eq!(solve_part(large.clone(), false, 8), Ok(unreduced_business(large, 8)));
+
+    let mut zero = monkeys.clone();
+    zero[2].test = Test::DivisibleBy(0);
+    assert_eq!(solve_part(zero, false, 1), Err(MonkeyError::ZeroDivisor { monkey: 2 }));
+
+    let mut lost = monkeys.clone();
+    lost[1].targets[1] = 4;
+    assert_eq!(solve_part(lost, false, 1), Err(MonkeyError::NoSuchMonkey { monkey: 1, target: 4 }));
+    assert_eq!(solve_part(monkeys[..1].to_vec(), true, 1), Err(MonkeyError::TooFewMonkeys));
+
+    // 2^63 + 1 is a multiple of 3, which only shows if it is loaded whole
+    let (_,mut large) = parser::parse("
+        Monkey 0:
+          Starting items: 9223372036854775809
+          Operation: new = old * 1
+          Test: divisible by 3
+            If true: throw to monkey 1
+            If false: throw to monkey 0
+
+        Monkey 1:
+          Starting items: 1
+          Operation: new = old * 1
+          Test: divisible by 5
+            If true: throw to monkey 0
+            If false: throw to monkey 0").unwrap();
+    let mut events = Vec::new();
+    run(&mut large, false, 1, |e| events.push(e.kind.clone())).unwrap();
+    assert_eq!(events[0], EventKind::Inspect { worry: BigInt::from(9223372036854775809u64) });
+    assert_eq!(events[3], EventKind::Throw { target: 1 });
+}
+
+#[test]
+fn test11_language() {
+    let reducible = "
+        Monkey 0:
+          Starting items: 10, 20
+          Operation: new = (old - 3) * 2 + 1
+          Test: modulo 3
+            If remainder is 2: throw to monkey 1
+            If remainder is 0: throw to monkey 2
+            If remainder is 1: throw to monkey 1
+
+        Monkey 1:
+          Starting items: 7, 5
+          Operation: new = old * (old + 4) - 9
+          Test: modulo 4 equals 1
+            If true: throw to monkey 2
+            If false: throw to monkey 0
+
+        Monkey 2:
+          Starting items: 3
+          Operation: new = 5 - old
+          Test: divisible by 7
+            If true: throw to monkey 0
+            If false: throw to monkey 1";
+    let (_,monkeys) = parser::parse(reducible).unwrap();
+    assert_eq!(monkeys[0].targets, vec![2, 1, 1]);
+    assert_eq!(monkeys[1].operation, Expr::Sub(
+        Box::new(Expr::Mul(
+            Box::new(Expr::Old),
+            Box::new(Expr::Add(Box::new(Expr::Old), Box::new(Expr::Value(4)))))),
+        Box::new(Expr::Value(9))));
+    assert_eq!(worry_modulus(&monkeys), BigUint::from(84u32));
+    assert_eq!(unreduced_business(monkeys.clone(), 8), 1330);
+    assert_eq!(solve_part(monkeys.clone(), false, 8), Ok(1330));
+    assert_eq!(solve_part(monkeys.clone(), false, 1000), Ok(24965010));
+    assert_eq!(fast_forward(&monkeys, 1000).map(|i| monkey_business(&i)), Ok(Some(24965010)));
+
+    let incomplete = reducible.replace("If remainder is 0", "If remainder is 3");
+    assert!(parser::parse(&incomplete).is_err());
+
+    let irreducible = "
+        Monkey 0:
+          Starting items: 79, 98
+          Operation: new = old * 19 / 4
+          Test: greater than 50
+            If true: throw to monkey 1
+            If false: throw to monkey 2
+
+        Monkey 1:
+          Starting items: 54, 65
+          Operation: new = (old + 6) - 100
+          Test: less than 0
+            If true: throw to monkey 2
+            If false: throw to monkey 0
+
+        Monkey 2:
+          Starting items: 3
+          Operation: new = old * old - (old - 1) / 2
+          Test: equal to 12
+            If true: throw to monkey 0
+            If false: throw to monkey 1";
+    let (_,monkeys) = parser::parse(irreducible).unwrap();
+    assert_eq!(solve_part(monkeys.clone(), true, 20), Ok(4312));
+    assert_eq!(solve_part(monkeys, false, 20), Err(MonkeyError::NotReducible { monkey: 0 }));
+
+    let by_zero = irreducible.replace("old * 19 / 4", "old / (old - old)");
+    let (_,monkeys) = parser::parse(&by_zero).unwrap();
+    assert_eq!(solve_part(monkeys, true, 20), Err(MonkeyError::DivisionByZero { monkey: 0 }));
+}
+
+#[test]
+fn test11_events() {
+    let (_,monkeys) = parser::parse(include_str!("../inputs/day11.1")).unwrap();
+
+    let mut output = Vec::new();
+    write_events(monkeys.clone(), true, 1, &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines[..4], [
+        r#"{"event":"inspect","item":0,"monkey":0,"origin":0,"round":1,"worry":79}"#,
+        r#"{"event":"worry","item":0,"monkey":0,"origin":0,"round":1,"worry":500}"#,
+        r#"{"event":"test","item":0,"monkey":0,"origin":0,"passed":false,"round":1}"#,
+        r#"{"event":"throw","item":0,"monkey":0,"origin":0,"round":1,"target":3}"#
+    ]);
+    // Every item of round 1 is inspected by each monkey it reaches
+    assert_eq!(lines.len(), 4 * (2 + 4 + 3 + 5));
+
+    let mut provenance = Provenance::new(&monkeys);
+    let mut events = 0;
+    run(&mut monkeys.clone(), false, 10000, |e| {
+        provenance.record(e);
+        events += 1;
+    }).unwrap();
+    assert_eq!(events, 4 * (52166 + 47830 + 1938 + 52013));
+    assert_eq!(provenance.items(), 10);
+    assert_eq!(provenance.origin(7), Some(2));
+    assert_eq!(provenance.holder(7, 0), Some(2));
+    assert_eq!(provenance.holder(7, 1), Some(1));
+    assert_eq!(provenance.holder(7, 500), Some(0));
+    assert_eq!(provenance.holder(7, 10000), Some(1));
+    assert_eq!(provenance.holder(10, 1), None);
+
+    let mut misnumbered = monkeys.clone();
+    misnumbered.swap(0, 1);
+    assert_eq!(solve_part(misnumbered, true, 1), Err(MonkeyError::Misnumbered { position: 0, id: 1 }));
+}
+
+#[cfg(test)]
+fn direct_inspections(mut monkeys: Vec<Monkey>, rounds: u32) -> Vec<u64> {
+    run(&mut monkeys, false, rounds, |_| ()).unwrap();
+    monkeys.iter().map(|m| m.inspections as u64).collect()
+}
+
+#[test]
+fn test11_fast_forward() {
+    for input in [include_str!("../inputs/day11.1"), include_str!("../inputs/day11.2")] {
+        let (_,monkeys) = parser::parse(input).unwrap();
+        for rounds in [0, 1, 20, 1000, 10000] {
+            assert_eq!(fast_forward(&monkeys, rounds as u64), Ok(direct_inspections(monkeys.clone(), rounds)));
+        }
+    }
+
+    // Item 0 reaches a cycle of 3 rounds after one, in which monkey 1 has it
+    // once. Item 1 joins the same cycle.
+    let tiny = "
+        Monkey 0:
+          Starting items: 4
+          Operation: new = old + 1
+          Test: divisible by 3
+            If true: throw to monkey 1
+            If false: throw to monkey 0
+
+        Monkey 1:
+          Starting items: 1
+          Operation: new = old * 1
+          Test: divisible by 3
+            If true: throw to monkey 0
+            If false: throw to monkey 0";
+    let (_,tiny) = parser::parse(tiny).unwrap();
+    let expected = |r: u64| vec![r + r.saturating_sub(1), (r + 1) / 3 + 1.min(r) + r / 3];
+    for rounds in 0..20 {
+        assert_eq!(direct_inspections(tiny.clone(), rounds as u32), expected(rounds));
+        assert_eq!(fast_forward(&tiny, rounds), Ok(expected(rounds)));
+    }
+    let inspections = fast_forward(&tiny, 1_000_000_000_000).unwrap();
+    assert_eq!(inspections, vec![1999999999999, 666666666667]);
+    assert_eq!(inspections, expected(1_000_000_000_000));
+
+    // 2^63 + 1 leaves the same remainder by 3 as 3 does
+    let mut large = tiny.clone();
+    large[0].items = vec![9223372036854775809];
+    let mut three = tiny.clone();
+    three[0].items = vec![3];
+    for rounds in 0..20 {
+        assert_eq!(fast_forward(&large, rounds), Ok(direct_inspections(large.clone(), rounds as u32)));
+    }
+    assert_eq!(fast_forward(&large, 1_000_000_000_000), fast_forward(&three, 1_000_000_000_000));
+
+    let (_,mut monkeys) = parser::parse(include_str!("../inputs/day11.1")).unwrap();
+    // Worry levels too large for the i128 path
+    for (m,d) in monkeys.iter_mut().zip([4294967291, 4294967279, 4294967231, 4294967197]) {
+        m.test = Test::DivisibleBy(d);
+    }
+    assert_eq!(fast_forward(&monkeys, 300), Ok(direct_inspections(monkeys, 300)));
+}
@@ -1,5 +1,5 @@
 use array2d::Array2D;
-use std::collections::VecDeque;
+use std::{cmp::Reverse, collections::BinaryHeap};
 
 mod parser {
     use nom::{
@@ -17,7 +17,7 @@ mod parser {
     }
 }
 
-type Point = (usize,usize);
+pub type Point = (usize,usize);
 
 const DIRECTIONS: [(i32,i32); 4] = [
     (1,0), (0,1), (-1, 0), (0, -1)
@@ -29,46 +29,226 @@ fn move_point(p: &Point, d: &(i32,i32)) -> Option<Point> {
         usize::try_from(p.1 as i32 + d.1).ok()?))
 }
 
-fn shortest_path(heightmap: &Array2D<u8>, starts: &[Point], end: &Point)
-        -> Option<u32> {
-    let mut queue: VecDeque<(u32,(usize,usize))>  = VecDeque::new();
+// The region a search managed to reach when it could not find the end
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub struct Unreachable {
+    pub reachable: Array2D<bool>
+}
 
-    for p in starts {
-        queue.push_back((0,*p));
+impl Unreachable {
+    pub fn size(&self) -> usize {
+        self.reachable.elements_row_major_iter().filter(|&&r| r).count()
     }
-    
-    let mut visits =
-        Array2D::filled_with(
-            false, 
-            heightmap.num_rows(),
-            heightmap.num_columns());
+}
 
-    while let Some ((distance,p)) = queue.pop_front() {
-        if p == *end {
-            return Some(distance);
+// Which steps are allowed between neighbouring cells and what they cost, as
+// a function of the height difference
+#[derive (Debug, Clone, Copy)]
+pub struct Rules<C = fn(i32) -> u64> {
+    pub max_ascent: u8,
+    pub max_descent: u8,
+    pub cost: C
+}
+
+// Climbing at most one level at a time, descending freely, one step at a time
+impl Default for Rules {
+    fn default() -> Rules {
+        Rules { max_ascent: 1, max_descent: u8::MAX, cost: |_| 1 }
+    }
+}
+
+impl<C: Fn(i32) -> u64> Rules<C> {
+    pub fn step_cost(&self, from: u8, to: u8) -> Option<u64> {
+        let allowed = if to >= from {
+            to - from <= self.max_ascent
+        } else {
+            from - to <= self.max_descent
+        };
+        allowed.then(|| (self.cost)(to as i32 - from as i32))
+    }
+}
+
+struct Search {
+    costs: Array2D<Option<u64>>,
+    // The neighbour each cell was reached from
+    previous: Array2D<Option<Point>>
+}
+
+// Dijkstra's algorithm from all the sources at once, stopping at the target
+// if there is one. Backwards searches follow the steps in reverse, finding
+// the cost from every cell to the sources. Ties are settled in the order
+// cells were reached, which makes unit costs explore like a breadth first
+// search.
+fn search<C: Fn(i32) -> u64>(heightmap: &Array2D<u8>, rules: &Rules<C>,
+        sources: &[Point], target: Option<Point>, backwards: bool) -> Search {
+    let (rows, columns) = (heightmap.num_rows(), heightmap.num_columns());
+    let mut costs = Array2D::filled_with(None, rows, columns);
+    let mut previous = Array2D::filled_with(None, rows, columns);
+    let mut done = Array2D::filled_with(false, rows, columns);
+    let mut queue = BinaryHeap::new();
+    let mut reached = 0u64;
+
+    for &p in sources {
+        if costs.set(p.0, p.1, Some(0)).is_ok() {
+            queue.push((Reverse(0), Reverse(reached), p));
+            reached += 1;
         }
+    }
 
-        let ph = *heightmap.get(p.0, p.1).unwrap();
+    while let Some((Reverse(cost), _, p)) = queue.pop() {
+        if done[p] {
+            continue;
+        }
+        done[p] = true;
+        if Some(p) == target {
+            break;
+        }
 
         for d in &DIRECTIONS {
-            if let Some(q) = move_point(&p, d) {
-                if let Some(false) = visits.get(q.0, q.1) {
-                    let qh = *heightmap.get(q.0, q.1).unwrap();
-                    if qh <= ph + 1 {
-                        visits.set(q.0, q.1, true).unwrap();
-                        queue.push_back((distance+1,q));
-                    }
+            let Some(q) = move_point(&p, d) else { continue };
+            let Some(&qh) = heightmap.get(q.0, q.1) else { continue };
+            let step = if backwards {
+                rules.step_cost(qh, heightmap[p])
+            } else {
+                rules.step_cost(heightmap[p], qh)
+            };
+            if let Some(step) = step {
+                let new_cost = cost + step;
+                if costs[q].is_none_or(|c| new_cost < c) {
+                    costs[q] = Some(new_cost);
+                    previous[q] = Some(p);
+                    queue.push((Reverse(new_cost), Reverse(reached), q));
+                    reached += 1;
                 }
             }
         }
     }
 
-    None
+    Search { costs, previous }
 }
 
-pub fn solve(input: &str) -> Option<(u32,u32)> {
-    let (_,data) = parser::parse(input).unwrap();
-    let data_array = Array2D::from_rows(&data).unwrap();
+// The cells leading back to a source, starting with the given one
+fn trace(previous: &Array2D<Option<Point>>, p: Point) -> Vec<Point> {
+    let mut path = vec![p];
+    let mut q = p;
+    while let Some(r) = previous[q] {
+        path.push(r);
+        q = r;
+    }
+    path
+}
+
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub cost: u64,
+    // From the start to the end, both included
+    pub path: Vec<Point>
+}
+
+// The cheapest route from any of the starts to the end
+pub fn cheapest_route<C: Fn(i32) -> u64>(heightmap: &Array2D<u8>, rules: &Rules<C>,
+        starts: &[Point], end: &Point) -> Result<Route, Unreachable> {
+    let Search { costs, previous } = search(heightmap, rules, starts, Some(*end), false);
+    match costs.get(end.0, end.1) {
+        Some(&Some(cost)) => {
+            let mut path = trace(&previous, *end);
+            path.reverse();
+            Ok(Route { cost, path })
+        },
+        _ => {
+            let reachable = Array2D::from_iter_row_major(
+                costs.elements_row_major_iter().map(|c| c.is_some()),
+                costs.num_rows(),
+                costs.num_columns()).unwrap();
+            Err(Unreachable { reachable })
+        }
+    }
+}
+
+// Returns the path from the closest start to the end, both included
+pub fn shortest_path(heightmap: &Array2D<u8>, starts: &[Point], end: &Point)
+        -> Result<Vec<Point>, Unreachable> {
+    cheapest_route(heightmap, &Rules::default(), starts, end).map(|r| r.path)
+}
+
+// The cost of the cheapest route from every cell to a single end
+pub struct DistanceMap {
+    costs: Array2D<Option<u64>>,
+    // The next cell on the way to the end
+    next: Array2D<Option<Point>>
+}
+
+impl DistanceMap {
+    pub fn to<C: Fn(i32) -> u64>(heightmap: &Array2D<u8>, rules: &Rules<C>, end: &Point)
+            -> DistanceMap {
+        let Search { costs, previous } = search(heightmap, rules, &[*end], None, true);
+        DistanceMap { costs, next: previous }
+    }
+
+    pub fn cost(&self, from: &Point) -> Option<u64> {
+        *self.costs.get(from.0, from.1)?
+    }
+
+    pub fn route(&self, from: &Point) -> Option<Route> {
+        let cost = self.cost(from)?;
+        Some(Route { cost, path: trace(&self.next, *from) })
+    }
+
+    // The start with the cheapest route, the first one listed in case of a tie
+    pub fn best_start<'a, I>(&self, starts: I) -> Option<(Point, u64)>
+            where I: IntoIterator<Item = &'a Point> {
+        starts.into_iter()
+            .filter_map(|p| Some((*p, self.cost(p)?)))
+            .min_by_key(|&(_,cost)| cost)
+    }
+}
+
+fn elevation(h: u8) -> char {
+    (b'a' + h) as char
+}
+
+// Draws the path over the heightmap, each step with an arrow pointing to the
+// next one, like in the puzzle statement
+pub fn render_path(heightmap: &Array2D<u8>, path: &[Point]) -> String {
+    let mut cells: Vec<Vec<char>> = heightmap.as_rows().iter()
+        .map(|row| row.iter().map(|&h| elevation(h)).collect())
+        .collect();
+    for step in path.windows(2) {
+        let (p, q) = (step[0], step[1]);
+        cells[p.0][p.1] = match (q.0 as i64 - p.0 as i64, q.1 as i64 - p.1 as i64) {
+            (1, 0) => 'v',
+            (-1, 0) => '^',
+            (0, 1) => '>',
+            _ => '<'
+        };
+    }
+    if let Some(end) = path.last() {
+        cells[end.0][end.1] = 'E';
+    }
+    cells.iter().map(|row| row.iter().collect::<String>() + "\n").collect()
+}
+
+// Draws the elevation of the cells that could be reached, and dots elsewhere
+pub fn render_region(heightmap: &Array2D<u8>, unreachable: &Unreachable) -> String {
+    let mut text = String::new();
+    for (heights, reached) in heightmap.as_rows().iter().zip(unreachable.reachable.as_rows()) {
+        for (&h, r) in heights.iter().zip(reached) {
+            text.push(if r { elevation(h) } else { '.' });
+        }
+        text.push('\n');
+    }
+    text
+}
+
+pub struct Hill {
+    pub heightmap: Array2D<u8>,
+    pub start: Point,
+    pub end: Point
+}
+
+pub fn parse_hill(input: &str) -> Option<Hill> {
+    let (_,data) = parser::parse(input).ok()?;
+    let data_array = Array2D::from_rows(&data).ok()?;
     let heightmap =
         Array2D::from_iter_row_major(
             data_array.elements_row_major_iter().map(|&c| match c {
@@ -78,20 +258,28 @@ pub fn solve(input: &str) -> Option<(u32,u32)> {
                 _ => panic!()
             }),
             data_array.num_rows(),
-            data_array.num_columns()).unwrap();
+            data_array.num_columns()).ok()?;
 
     let (start,_) =
         data_array.enumerate_row_major().find(|(_,c)| **c == 'S')?;
     let (end,_) =
         data_array.enumerate_row_major().find(|(_,c)| **c == 'E')?;
+    Some(Hill { heightmap, start, end })
+}
+
+pub fn solve(input: &str) -> Option<(u32,u32)> {
+    let Hill { heightmap, start, end } = parse_hill(input)?;
     let lowest_points: Vec<Point> =
         heightmap.enumerate_row_major()
             .filter(|(_,c)| **c == 0)
             .map(|(p,_)| p)
             .collect();
 
-    let solution1 = shortest_path(&heightmap, &[start], &end)?;
-    let solution2 = shortest_path(&heightmap, &lowest_points, &end)?;
+    // Every start is answered by a single search from the end
+    let distances = DistanceMap::to(&heightmap, &Rules::default(), &end);
+    let solution1 = distances.cost(&start)? as u32;
+    let (_,solution2) = distances.best_start(&lowest_points)?;
+    let solution2 = solution2 as u32;
 
 
     Some((solution1, solution2))
@@ -108,3 +296,70 @@ fn test12_2() {
     let solution = solve(&include_str!("../inputs/day12.2"));
     assert_eq!(solution, Some((420,414)));
 }
+
+#[test]
+fn test12_path() {
+    let hill = parse_hill(include_str!("../inputs/day12.1")).unwrap();
+    let path = shortest_path(&hill.heightmap, &[hill.start], &hill.end).unwrap();
+    assert_eq!(path.len(), 32);
+    assert_eq!((path[0], path[31]), (hill.start, hill.end));
+    for step in path.windows(2) {
+        let (p, q) = (step[0], step[1]);
+        assert_eq!(p.0.abs_diff(q.0) + p.1.abs_diff(q.1), 1);
+        assert!(hill.heightmap[q] <= hill.heightmap[p] + 1);
+    }
+    assert_eq!(render_path(&hill.heightmap, &path), "\
+vabv<<<<
+>vcvv<<^
+avcv>E^^
+a>v>>>^^
+ab>>>>>^
+");
+}
+
+#[test]
+fn test12_unreachable() {
+    let hill = parse_hill("Sbz\nzzE\n").unwrap();
+    let unreachable = shortest_path(&hill.heightmap, &[hill.start], &hill.end).unwrap_err();
+    assert_eq!(unreachable.size(), 2);
+    assert_eq!(render_region(&hill.heightmap, &unreachable), "ab.\n...\n");
+}
+
+#[test]
+fn test12_rules() {
+    let hill = parse_hill(include_str!("../inputs/day12.2")).unwrap();
+    let rules = Rules {
+        max_ascent: 2,
+        max_descent: 3,
+        cost: |d: i32| 1 + (d * d) as u64
+    };
+    let route = cheapest_route(&hill.heightmap, &rules, &[hill.start], &hill.end).unwrap();
+    assert_eq!(route.cost, 349);
+    let total: u64 = route.path.windows(2)
+        .map(|s| rules.step_cost(hill.heightmap[s[0]], hill.heightmap[s[1]]).unwrap())
+        .sum();
+    assert_eq!(total, 349);
+
+    let distances = DistanceMap::to(&hill.heightmap, &rules, &hill.end);
+    assert_eq!(distances.cost(&hill.start), Some(349));
+    let lowest_points: Vec<Point> = hill.heightmap.enumerate_row_major()
+        .filter(|&((_,j),&h)| h == 0 && j < 3)
+        .map(|(p,_)| p)
+        .collect();
+    let (best, cost) = distances.best_start(&lowest_points).unwrap();
+    assert_eq!(cost, 342);
+    let route = distances.route(&best).unwrap();
+    assert_eq!((route.path[0], route.path[route.path.len()-1]), (best, hill.end));
+    assert_eq!(cheapest_route(&hill.heightmap, &rules, &[best], &hill.end).map(|r| r.cost), Ok(342));
+
+    // Peaks next to the end can only be left by going down
+    let ridge = parse_hill("SzyEyz\n").unwrap();
+    let distances = DistanceMap::to(&ridge.heightmap, &Rules::default(), &ridge.end);
+    assert_eq!(distances.cost(&(0, 1)), Some(2));
+    let rules = Rules { max_descent: 0, ..Rules::default() };
+    let distances = DistanceMap::to(&ridge.heightmap, &rules, &ridge.end);
+    assert_eq!(distances.cost(&(0, 1)), None);
+    assert_eq!(distances.cost(&(0, 2)), Some(1));
+    assert_eq!(distances.cost(&(0, 4)), Some(1));
+    assert_eq!(distances.cost(&ridge.start), None);
+}
@@ -1,45 +1,314 @@
-use std::{slice, cmp::Ordering, fmt};
+use std::{slice, cmp::Ordering, convert::Infallible, fmt, str::FromStr};
 
-#[derive (Debug, PartialEq, Eq, Clone)]
+use serde_json::Value;
+
+// Integers are i64s. serde_json keeps integers of that range exact instead
+// of going through a double, so conversions lose nothing, even above 2^53.
 pub enum Packet {
-    Int(i32),
+    Int(i64),
     List(Vec<Packet>)
 }
 
-impl Ord for Packet {
-    fn cmp(&self, other: &Self) -> Ordering {
-        fn cmp_packet(p1: &Packet, p2: &Packet) -> Ordering {
-            match (p1,p2) {
-                (Packet::Int(i1), Packet::Int(i2)) =>
-                    i1.cmp(i2),
-                (Packet::Int(_), Packet::List(l2)) =>
-                    cmp_slices(slice::from_ref(p1), l2),
-                (Packet::List(l1), Packet::Int(_)) =>
-                    cmp_slices(l1, slice::from_ref(p2)),
-                (Packet::List(l1), Packet::List(l2)) =>
-                    cmp_slices(l1, l2)
+impl Packet {
+    // The elements an integer is compared with once promoted to a list
+    fn elements(&self) -> &[Packet] {
+        match self {
+            Packet::Int(_) => slice::from_ref(self),
+            Packet::List(l) => l
+        }
+    }
+
+    fn items(&self) -> Option<&[Packet]> {
+        match self {
+            Packet::Int(_) => None,
+            Packet::List(l) => Some(l)
+        }
+    }
+
+    // Moves the elements out of a list, which patterns cannot do as packets
+    // implement Drop
+    pub fn into_items(mut self) -> Option<Vec<Packet>> {
+        match &mut self {
+            Packet::Int(_) => None,
+            Packet::List(l) => Some(std::mem::take(l))
+        }
+    }
+
+    // Writes the packet with the given punctuation
+    fn write(&self, f: &mut fmt::Formatter, open: &str, separator: &str, close: &str,
+            int: fn(&mut fmt::Formatter, i64) -> fmt::Result) -> fmt::Result {
+        // The elements left to write at each level, and whether any was
+        // written yet. The bottom level only holds this packet.
+        let mut stack = vec![(slice::from_ref(self).iter(), false)];
+
+        while let Some((elements, started)) = stack.last_mut() {
+            match elements.next() {
+                None => {
+                    stack.pop();
+                    if !stack.is_empty() {
+                        write!(f, "{close}")?;
+                    }
+                },
+                Some(p) => {
+                    if *started {
+                        write!(f, "{separator}")?;
+                    }
+                    *started = true;
+                    match p {
+                        Packet::Int(i) => int(f, *i)?,
+                        Packet::List(l) => {
+                            write!(f, "{open}")?;
+                            stack.push((l.iter(), false));
+                        }
+                    }
+                }
             }
         }
+        Ok(())
+    }
+}
+
+// Converts a tree into another one level by level. Nodes with children are
+// rebuilt from their converted children by list, the others by leaf.
+fn rebuild<'a, S, T, E>(root: &'a S,
+        children: impl Fn(&'a S) -> Option<&'a [S]>,
+        leaf: impl Fn(&'a S) -> Result<T, E>,
+        list: impl Fn(Vec<T>) -> T) -> Result<T, E> {
+    // The nodes left at each level with what they were converted to. The
+    // bottom level only holds the root.
+    let mut stack = vec![(slice::from_ref(root).iter(), Vec::with_capacity(1))];
+
+    while let Some((nodes, converted)) = stack.last_mut() {
+        if let Some(node) = nodes.next() {
+            match children(node) {
+                Some(c) => stack.push((c.iter(), Vec::with_capacity(c.len()))),
+                None => converted.push(leaf(node)?)
+            }
+            continue;
+        }
+
+        let mut converted = std::mem::take(converted);
+        stack.pop();
+        match stack.last_mut() {
+            Some((_, parent)) => parent.push(list(converted)),
+            None => if let Some(root) = converted.pop() {
+                return Ok(root);
+            }
+        }
+    }
+    unreachable!("the bottom level always converts the root")
+}
+
+impl Clone for Packet {
+    fn clone(&self) -> Packet {
+        let copy = rebuild(self, Packet::items,
+            |p| Ok::<_, Infallible>(match p {
+                Packet::Int(i) => Packet::Int(*i),
+                Packet::List(_) => Packet::List(Vec::new())
+            }),
+            Packet::List);
+        match copy {
+            Ok(p) => p,
+            Err(e) => match e {}
+        }
+    }
+}
+
+// Structural equality, unlike the ordering which promotes integers to lists
+impl PartialEq for Packet {
+    fn eq(&self, other: &Self) -> bool {
+        let mut stack = vec![(slice::from_ref(self), slice::from_ref(other))];
+
+        while let Some((l1, l2)) = stack.pop() {
+            if l1.len() != l2.len() {
+                return false;
+            }
+            for pair in l1.iter().zip(l2) {
+                match pair {
+                    (Packet::Int(i1), Packet::Int(i2)) if i1 == i2 => (),
+                    (Packet::List(l1), Packet::List(l2)) => stack.push((l1, l2)),
+                    _ => return false
+                }
+            }
+        }
+        true
+    }
+}
+
+impl Eq for Packet {}
+
+// Written as the derived implementation would, always on one line
+impl fmt::Debug for Packet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, "List([", ", ", "])", |f, i| write!(f, "Int({i})"))
+    }
+}
 
-        fn cmp_slices(l1: &[Packet], l2: &[Packet]) -> Ordering {
+// Nested lists are taken apart one at a time rather than recursively, which
+// would overflow the stack on deep packets
+impl Drop for Packet {
+    fn drop(&mut self) {
+        let Packet::List(l) = self else { return };
+        let mut stack = std::mem::take(l);
+        while let Some(mut p) = stack.pop() {
+            if let Packet::List(l) = &mut p {
+                stack.append(l);
+            }
+        }
+    }
+}
+
+impl Ord for Packet {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // The remaining elements of the lists being compared at each level
+        let mut stack = vec![(slice::from_ref(self), slice::from_ref(other))];
+
+        while let Some((l1, l2)) = stack.pop() {
             match (l1.split_first(), l2.split_first()) {
-                (None, None) => Ordering::Equal,
-                (None, Some (_)) => Ordering::Less,
-                (Some (_) , None) => Ordering::Greater,
+                (None, None) => (),
+                (None, Some (_)) => return Ordering::Less,
+                (Some (_) , None) => return Ordering::Greater,
                 (Some ((h1,t1)), Some ((h2,t2))) => {
-                    let r = cmp_packet(h1, h2);
-                    if r != Ordering::Equal {
-                        r
-                    }
-                    else {
-                        cmp_slices(t1, t2)
+                    stack.push((t1, t2));
+                    match (h1, h2) {
+                        (Packet::Int(i1), Packet::Int(i2)) => {
+                            let r = i1.cmp(i2);
+                            if r != Ordering::Equal {
+                                return r;
+                            }
+                        },
+                        _ => stack.push((h1.elements(), h2.elements()))
                     }
                 }
             }
         }
-        
-        cmp_packet(self, other)
+        Ordering::Equal
+    }
+}
+
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side { Left, Right }
+
+// A step towards the elements that decide the order of two packets
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    // The elements at this index of both lists
+    Index(usize),
+    // An integer on one side compared with a list, as a list of one
+    Promote(Side)
+}
+
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compared {
+    Ints(i64, i64),
+    // One of the lists ran out first
+    Lengths(usize, usize)
+}
+
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub enum Explanation {
+    Equal,
+    Differ { path: Vec<Step>, compared: Compared, verdict: Ordering }
+}
+
+impl Explanation {
+    pub fn verdict(&self) -> Ordering {
+        match self {
+            Explanation::Equal => Ordering::Equal,
+            Explanation::Differ { verdict, .. } => *verdict
+        }
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (path, compared, verdict) = match self {
+            Explanation::Equal => return write!(f, "equal"),
+            Explanation::Differ { path, compared, verdict } => (path, compared, verdict)
+        };
+        write!(f, "root")?;
+        for step in path {
+            match step {
+                Step::Index(i) => write!(f, "[{i}]")?,
+                Step::Promote(Side::Left) => write!(f, "{{left promoted}}")?,
+                Step::Promote(Side::Right) => write!(f, "{{right promoted}}")?
+            }
+        }
+        match compared {
+            Compared::Ints(i1, i2) => write!(f, ": {i1} vs {i2}")?,
+            Compared::Lengths(l1, l2) => write!(f, ": length {l1} vs {l2}")?
+        }
+        let verdict = if *verdict == Ordering::Less { "right order" } else { "wrong order" };
+        write!(f, ", {verdict}")
+    }
+}
+
+// Finds what decides the order of two packets, following the same steps
+// as the comparison
+pub fn explain(p1: &Packet, p2: &Packet) -> Explanation {
+    struct Level<'a> {
+        l1: &'a [Packet],
+        l2: &'a [Packet],
+        index: usize,
+        promoted: Option<Side>
+    }
+
+    let path = |levels: &[Level], with_index: bool| {
+        let mut path = Vec::new();
+        for (i,level) in levels.iter().enumerate().skip(1) {
+            path.extend(level.promoted.map(Step::Promote));
+            if with_index || i < levels.len() - 1 {
+                path.push(Step::Index(level.index));
+            }
+        }
+        path
+    };
+
+    // The bottom level only holds the packets themselves
+    let mut levels = vec![Level {
+        l1: slice::from_ref(p1),
+        l2: slice::from_ref(p2),
+        index: 0,
+        promoted: None
+    }];
+
+    while let Some(level) = levels.last_mut() {
+        let (l1, l2, index) = (level.l1, level.l2, level.index);
+        match (l1.get(index), l2.get(index)) {
+            (None, None) => {
+                levels.pop();
+                if let Some(parent) = levels.last_mut() {
+                    parent.index += 1;
+                }
+            },
+            (None, Some(_)) | (Some(_), None) => {
+                let verdict = l1.len().cmp(&l2.len());
+                let compared = Compared::Lengths(l1.len(), l2.len());
+                return Explanation::Differ { path: path(&levels, false), compared, verdict };
+            },
+            (Some(Packet::Int(i1)), Some(Packet::Int(i2))) => {
+                if i1 != i2 {
+                    let compared = Compared::Ints(*i1, *i2);
+                    return Explanation::Differ { path: path(&levels, true), compared, verdict: i1.cmp(i2) };
+                }
+                level.index += 1;
+            },
+            (Some(h1), Some(h2)) => {
+                let promoted = match (h1, h2) {
+                    (Packet::Int(_), _) => Some(Side::Left),
+                    (_, Packet::Int(_)) => Some(Side::Right),
+                    _ => None
+                };
+                levels.push(Level { l1: h1.elements(), l2: h2.elements(), index: 0, promoted });
+            }
+        }
     }
+    Explanation::Equal
+}
+
+// Explains the order of each pair of packets, an odd one out being ignored
+pub fn explain_pairs(packets: &[Packet]) -> Vec<Explanation> {
+    packets.chunks_exact(2).map(|pair| explain(&pair[0], &pair[1])).collect()
 }
 
 impl PartialOrd for Packet {
@@ -50,93 +319,207 @@ impl PartialOrd for Packet {
 
 impl fmt::Display for Packet {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Packet::Int(i) => write!(f, "{i}"),
-            Packet::List(l) => {
-                let mut first = true;
-                write!(f, "[")?;
-                for p in l {
-                    if first {
-                        first = false
-                    }
-                    else {
-                        write!(f, ", ")?;
-                    }
-                    p.fmt(f)?;
-                }
-                write!(f, "]")
-            }
-        }
+        self.write(f, "[", ",", "]", |f, i| write!(f, "{i}"))
     }
 }
 
+// Deep enough for any sensible packet
+pub const MAX_DEPTH: usize = 1000;
+
 mod parser {
     use nom::{
         IResult,
-        character::complete::*,
-        combinator::*,
-        sequence::*,
-        multi::*,
-        branch::*,
-        bytes::complete::*
+        character::complete::*
     };
 
     use super::*;
 
-    fn packet(input: &str) -> IResult<&str, Packet> {
-        alt((
-            map(i32, Packet::Int),
-            map(
-                delimited(
-                    tag("["), 
-                    separated_list0(tag(","), packet),
-                    tag("]")),
-                Packet::List)))(input)
+    fn skip_space(input: &str) -> &str {
+        let r: IResult<&str, &str> = multispace0(input);
+        r.map_or(input, |(rest,_)| rest)
     }
 
-    pub fn parse(input: &str) -> IResult<&str, Vec<Packet>> {
-        let data = separated_list1(multispace1, packet);
-        all_consuming(terminated(data, multispace0))(input)
+    fn error(input: &str, rest: &str, expected: &str) -> PacketError {
+        let offset = input.len() - rest.len();
+        PacketError::Parse(format!("expected {expected} at offset {offset}"))
     }
-}
 
-pub fn solve(input: &str) -> Option<(u32,u32)> {
-    let (_,data) = parser::parse(input).unwrap();
-    let mut solution1 = 0;
+    // Reads a packet with lists nested at most max_depth deep, keeping the
+    // lists being read on a stack
+    pub fn packet(input: &str, max_depth: usize) -> Result<(&str, Packet), PacketError> {
+        let mut stack: Vec<Vec<Packet>> = Vec::new();
+        let mut rest = input;
 
-    for (i,chunk) in data.chunks(2).enumerate() {
-        let (p1,p2) = match chunk {
-            [p1,p2] => (p1,p2),
-            _ => panic!()
-        };
-        let c = p1.partial_cmp(p2);
-        if c == Some(Ordering::Less) {
-            solution1 += (i as u32) + 1;
+        loop {
+            let mut p = if let Some(r) = rest.strip_prefix('[') {
+                if stack.len() == max_depth {
+                    return Err(PacketError::TooDeep { limit: max_depth });
+                }
+                rest = skip_space(r);
+                match rest.strip_prefix(']') {
+                    Some(r) => {
+                        rest = r;
+                        Packet::List(Vec::new())
+                    },
+                    None => {
+                        stack.push(Vec::new());
+                        continue;
+                    }
+                }
+            }
+            else {
+                let r: IResult<&str, i64> = i64(rest);
+                let (r, i) = r.map_err(|_| error(input, rest, "a packet"))?;
+                rest = r;
+                Packet::Int(i)
+            };
+
+            // Closes the lists p completes
+            loop {
+                let Some(list) = stack.last_mut() else {
+                    return Ok((rest, p));
+                };
+                list.push(p);
+                rest = skip_space(rest);
+                if let Some(r) = rest.strip_prefix(',') {
+                    rest = skip_space(r);
+                    break;
+                }
+                rest = rest.strip_prefix(']').ok_or_else(|| error(input, rest, "',' or ']'"))?;
+                p = Packet::List(stack.pop().unwrap_or_default());
+            }
         }
-        else if c == Some(Ordering::Greater) {
-            // Wrong order
+    }
+
+    pub fn single(input: &str, max_depth: usize) -> Result<Packet, PacketError> {
+        let (rest, p) = packet(skip_space(input), max_depth)?;
+        match skip_space(rest) {
+            "" => Ok(p),
+            rest => Err(error(input, rest, "the end"))
         }
-        else {
-            println!("{p1} {c:?} {p2}");
-            panic!()
+    }
+
+    pub fn parse(input: &str, max_depth: usize) -> Result<Vec<Packet>, PacketError> {
+        let mut packets = Vec::new();
+        let mut rest = skip_space(input);
+        while !rest.is_empty() {
+            let (r, p) = packet(rest, max_depth)?;
+            packets.push(p);
+            rest = skip_space(r);
+            if rest.len() == r.len() && !rest.is_empty() {
+                return Err(error(input, rest, "a space"));
+            }
         }
+        Ok(packets)
     }
+}
 
-    let mut signal = data;
-    let divider1 = Packet::List(vec![Packet::Int(2)]);
-    let divider2 = Packet::List(vec![Packet::Int(6)]);
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub enum PacketError {
+    Parse(String),
+    TooDeep { limit: usize },
+    // A JSON value that is neither an integer nor an array
+    NotAPacket(Value)
+}
 
-    signal.push(divider1.clone());
-    signal.push(divider2.clone());
-    signal.sort();
+impl fmt::Display for PacketError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PacketError::Parse(e) => write!(f, "invalid packet: {e}"),
+            PacketError::TooDeep { limit } =>
+                write!(f, "lists are nested more than {limit} deep"),
+            PacketError::NotAPacket(v) => write!(f, "{v} is not a packet")
+        }
+    }
+}
+
+impl std::error::Error for PacketError {}
 
-    let solution2 =
-        ((signal.iter().position(|x| x == &divider1)? + 1) *
-        (signal.iter().position(|x| x == &divider2)? + 1)) as u32;
+impl FromStr for Packet {
+    type Err = PacketError;
 
-    for p in signal {
-        println!("{p}");
+    fn from_str(s: &str) -> Result<Packet, PacketError> {
+        parse_packet(s, MAX_DEPTH)
     }
+}
+
+pub fn parse_packet(input: &str, max_depth: usize) -> Result<Packet, PacketError> {
+    parser::single(input, max_depth)
+}
+
+// Packets separated by white space
+pub fn parse_packets(input: &str, max_depth: usize) -> Result<Vec<Packet>, PacketError> {
+    parser::parse(input, max_depth)
+}
+
+impl From<&Packet> for Value {
+    fn from(p: &Packet) -> Value {
+        let value = rebuild(p, Packet::items,
+            |p| Ok::<_, Infallible>(match p {
+                Packet::Int(i) => Value::from(*i),
+                Packet::List(_) => Value::Array(Vec::new())
+            }),
+            Value::Array);
+        match value {
+            Ok(v) => v,
+            Err(e) => match e {}
+        }
+    }
+}
+
+impl TryFrom<&Value> for Packet {
+    type Error = PacketError;
+
+    fn try_from(v: &Value) -> Result<Packet, PacketError> {
+        rebuild(v,
+            |v| v.as_array().map(|l| l.as_slice()),
+            |v| v.as_i64()
+                .map(Packet::Int)
+                .ok_or_else(|| PacketError::NotAPacket(v.clone())),
+            Packet::List)
+    }
+}
+
+// The packets and the dividers in order. Packets that compare equal keep the
+// order they are listed in, the dividers coming after the packets.
+pub fn sort_signal<'a>(packets: &'a [Packet], dividers: &'a [Packet]) -> Vec<&'a Packet> {
+    let mut signal: Vec<&Packet> = packets.iter().chain(dividers).collect();
+    signal.sort();
+    signal
+}
+
+// The position each divider takes in the sorted signal, counting from 1, as
+// found by comparing it with every other packet
+pub fn divider_positions(packets: &[Packet], dividers: &[Packet]) -> Vec<usize> {
+    dividers.iter().enumerate()
+        .map(|(i,d)| {
+            let packets_before = packets.iter().filter(|p| *p <= d).count();
+            let dividers_before = dividers.iter().enumerate()
+                .filter(|&(j,other)| match other.cmp(d) {
+                    Ordering::Less => true,
+                    Ordering::Equal => j < i,
+                    Ordering::Greater => false
+                })
+                .count();
+            packets_before + dividers_before + 1
+        })
+        .collect()
+}
+
+pub fn solve(input: &str) -> Option<(u32,u32)> {
+    let data = parse_packets(input, MAX_DEPTH).unwrap();
+
+    // Equal pairs are neither in the right order nor in the wrong one
+    let solution1 = explain_pairs(&data).iter().enumerate()
+        .filter(|(_,e)| e.verdict() == Ordering::Less)
+        .map(|(i,_)| i as u32 + 1)
+        .sum();
+
+    let dividers = [
+        Packet::List(vec![Packet::Int(2)]),
+        Packet::List(vec![Packet::Int(6)])
+    ];
+    let solution2 = divider_positions(&data, &dividers).iter().product::<usize>() as u32;
 
     Some((solution1, solution2))
 }
@@ -152,3 +535,167 @@ fn test13_2() {
     let solution = solve(&include_str!("../inputs/day13.2"));
     assert_eq!(solution, Some((6478,21922)));
 }
+
+#[test]
+fn test13_json() {
+    for input in [include_str!("../inputs/day13.1"), include_str!("../inputs/day13.2")] {
+        for line in input.lines().filter(|l| !l.is_empty()) {
+            let packet: Packet = line.parse().unwrap();
+            assert_eq!(packet.to_string(), line);
+
+            let value: Value = serde_json::from_str(line).unwrap();
+            assert_eq!(Value::from(&packet), value);
+            assert_eq!(Packet::try_from(&value), Ok(packet.clone()));
+
+            let pretty = serde_json::to_string_pretty(&value).unwrap();
+            assert_eq!(pretty.parse(), Ok(packet));
+        }
+    }
+
+    let packet: Packet = " [ 9223372036854775807 ,[ -3 ] ,[]]\n".parse().unwrap();
+    assert_eq!(packet, Packet::List(vec![
+        Packet::Int(i64::MAX),
+        Packet::List(vec![Packet::Int(-3)]),
+        Packet::List(vec![])]));
+    assert_eq!(packet.to_string(), "[9223372036854775807,[-3],[]]");
+
+    assert!(matches!("[1,]".parse::<Packet>(), Err(PacketError::Parse(_))));
+    assert!("[1] [2]".parse::<Packet>().is_err());
+    let value = serde_json::json!([1, [2, "three"]]);
+    assert_eq!(Packet::try_from(&value), Err(PacketError::NotAPacket(serde_json::json!("three"))));
+    let value = serde_json::json!([u64::MAX]);
+    assert!(Packet::try_from(&value).is_err());
+
+    // 2^53 + 1, which a double would round to 2^53
+    let line = "[9007199254740993,[-9007199254740993]]";
+    let value: Value = serde_json::from_str(line).unwrap();
+    let packet = Packet::try_from(&value).unwrap();
+    assert_eq!(packet, Packet::List(vec![
+        Packet::Int(9007199254740993),
+        Packet::List(vec![Packet::Int(-9007199254740993)])]));
+    assert_eq!(Value::from(&packet), value);
+    assert_eq!(serde_json::to_string(&Value::from(&packet)).unwrap(), line);
+}
+
+#[test]
+fn test13_deep() {
+    let long = |last: i64| {
+        let elements: Vec<String> = (0..1_000_000).map(|i| i.to_string()).collect();
+        format!("[{},{last}]", elements.join(" , "))
+    };
+    let (text1, text2) = (long(1), long(2));
+    let p1 = parse_packet(&text1, MAX_DEPTH).unwrap();
+    let p2 = parse_packet(&text2, MAX_DEPTH).unwrap();
+    assert_eq!(p1.cmp(&p2), Ordering::Less);
+    assert_eq!(p1.to_string(), text1.replace(" , ", ","));
+
+    let depth = 100_000;
+    let nested = |inner: &str| format!("{}{inner}{}", "[".repeat(depth), "]".repeat(depth));
+    let (text1, text2) = (nested("1,2"), nested("1,[[3]]"));
+    let p1 = parse_packet(&text1, depth).unwrap();
+    let p2 = parse_packet(&text2, depth + 2).unwrap();
+    assert_eq!(p1.cmp(&p2), Ordering::Less);
+    assert_eq!(p2.cmp(&p1), Ordering::Greater);
+    assert_eq!(p1.to_string(), text1);
+    // An integer compares equal to itself wrapped in any number of lists
+    let p3 = parse_packet(&nested("1"), depth).unwrap();
+    assert_eq!(Packet::Int(1).cmp(&p3), Ordering::Equal);
+
+    assert_eq!(parse_packet(&text1, depth - 1), Err(PacketError::TooDeep { limit: depth - 1 }));
+    assert_eq!(text1.parse::<Packet>(), Err(PacketError::TooDeep { limit: MAX_DEPTH }));
+    assert_eq!(parse_packet("[[1],[2,3]]", 2).map(|p| p.to_string()), Ok(String::from("[[1],[2,3]]")));
+    assert_eq!(parse_packet("[[[]]]", 2), Err(PacketError::TooDeep { limit: 2 }));
+    assert_eq!(parse_packets("[1] [2]\n\n[]", 1).map(|p| p.len()), Ok(3));
+    assert!(parse_packets("[1][2]", 1).is_err());
+}
+
+#[test]
+fn test13_explain() {
+    let data = parse_packets(include_str!("../inputs/day13.1"), MAX_DEPTH).unwrap();
+    let explanations: Vec<String> = explain_pairs(&data).iter().map(|e| e.to_string()).collect();
+    assert_eq!(explanations, [
+        "root[2]: 3 vs 5, right order",
+        "root[1]{right promoted}[0]: 2 vs 4, right order",
+        "root[0]{left promoted}[0]: 9 vs 8, wrong order",
+        "root: length 3 vs 4, right order",
+        "root: length 4 vs 3, wrong order",
+        "root: length 0 vs 1, right order",
+        "root[0]: length 1 vs 0, wrong order",
+        "root[1][1][1][1][2]: 7 vs 0, wrong order"
+    ]);
+
+    for input in [include_str!("../inputs/day13.1"), include_str!("../inputs/day13.2")] {
+        let data = parse_packets(input, MAX_DEPTH).unwrap();
+        for p1 in &data {
+            for p2 in data.iter().take(20) {
+                assert_eq!(explain(p1, p2).verdict(), p1.cmp(p2));
+            }
+        }
+    }
+
+    let explain_text = |t1: &str, t2: &str| explain(&t1.parse().unwrap(), &t2.parse().unwrap());
+    assert_eq!(explain_text("[1,[2]]", "[1,2]"), Explanation::Equal);
+    assert_eq!(explain_text("5", "[[]]"), Explanation::Differ {
+        path: vec![Step::Promote(Side::Left), Step::Index(0), Step::Promote(Side::Left)],
+        compared: Compared::Lengths(1, 0),
+        verdict: Ordering::Greater
+    });
+    assert_eq!(solve("[1]\n[1]\n\n[1]\n[2]\n\n[[1]]\n[1]\n").map(|s| s.0), Some(2));
+}
+
+#[test]
+fn test13_dividers() {
+    let parse = |text: &str| parse_packets(text, MAX_DEPTH).unwrap();
+    let dividers = parse("[[2]] [[6]] [] [[2]] [3] [1,[2,[3,[4,[5,6,0]]]],8,9] 5");
+
+    for input in [include_str!("../inputs/day13.1"), include_str!("../inputs/day13.2")] {
+        let data = parse(input);
+        let signal = sort_signal(&data, &dividers);
+        let sorted_positions: Vec<usize> = dividers.iter()
+            .map(|d| signal.iter().position(|&p| std::ptr::eq(p, d)).unwrap() + 1)
+            .collect();
+        assert_eq!(divider_positions(&data, &dividers), sorted_positions);
+    }
+
+    let data = parse(include_str!("../inputs/day13.1"));
+    assert_eq!(divider_positions(&data, &dividers[..2]), vec![10, 14]);
+    assert_eq!(divider_positions(&[], &dividers[..2]), vec![1, 2]);
+}
+
+// Values are dropped recursively, which deep ones cannot afford
+#[cfg(test)]
+fn drop_value(v: Value) {
+    let mut stack = vec![v];
+    while let Some(mut v) = stack.pop() {
+        if let Value::Array(l) = &mut v {
+            stack.append(l);
+        }
+    }
+}
+
+#[test]
+fn test13_deep_traits() {
+    let depth = 100_000;
+    let nested = |inner: &str| format!("{}{inner}{}", "[".repeat(depth), "]".repeat(depth));
+    let p1 = parse_packet(&nested("1,2"), depth).unwrap();
+    let p2 = parse_packet(&nested("1,3"), depth).unwrap();
+
+    let copy = p1.clone();
+    assert!(copy == p1);
+    assert!(copy != p2);
+    let debug = format!("{p1:?}");
+    assert!(debug.starts_with("List([List(["));
+    assert!(debug.contains("[List([Int(1), Int(2)])])"));
+    assert!(debug.ends_with("])])"));
+
+    let value = Value::from(&p1);
+    assert!(Packet::try_from(&value).unwrap() == p1);
+    drop_value(value);
+
+    let mut outer = p1;
+    for _ in 0..depth - 1 {
+        outer = outer.into_items().unwrap().pop().unwrap();
+    }
+    assert_eq!(outer.into_items(), Some(vec![Packet::Int(1), Packet::Int(2)]));
+    assert_eq!(Packet::Int(1).into_items(), None);
+}
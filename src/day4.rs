@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 type Interval = (u32,u32);
 
 mod parser  {
@@ -28,6 +30,82 @@ pub fn overlaps(&(l1,u1): &Interval, &(l2,u2): &Interval) -> bool {
     u1 >= l2 && l1 <= u2
 }
 
+#[derive (Debug, PartialEq, Eq)]
+pub struct Sweep {
+    pub max_coverage: usize,
+    pub uncovered: Vec<Interval>,
+    pub overlapping_pairs: Vec<(usize,usize)>
+}
+
+// Sweeps over all assignments at once. Interval ends are shifted by one so
+// that, at equal positions, intervals leaving are processed before those
+// entering. Overlapping pairs are reported each time an interval enters
+// while another pair's interval is active, so up to four times each, and the
+// repeats are sorted away: O(n log n + k log k) for k overlapping pairs.
+// Uncovered sections are those of the universe no assignment reaches.
+pub fn sweep(data: &[(Interval,Interval)], universe: Interval) -> Sweep {
+    let mut events: Vec<(u64,bool,usize)> = Vec::with_capacity(4 * data.len());
+
+    for (i,(x1,x2)) in data.iter().enumerate() {
+        for &(l,u) in [x1,x2] {
+            events.push((l as u64, true, i));
+            events.push((u as u64 + 1, false, i));
+        }
+    }
+    events.sort_unstable();
+
+    let mut active: HashMap<usize,u32> = HashMap::new();
+    let mut coverage = 0;
+    let mut max_coverage = 0;
+    let mut uncovered = Vec::new();
+    let mut overlapping_pairs = Vec::new();
+    let mut last_uncovered = Some(universe.0 as u64);
+    let mut gap = |start: u64, end: u64| {
+        let (start, end) = (start.max(universe.0 as u64), end.min(universe.1 as u64));
+        if start <= end {
+            uncovered.push((start as u32, end as u32));
+        }
+    };
+
+    for (pos,entering,i) in events {
+        if entering {
+            if let Some(start) = last_uncovered.take() {
+                if start < pos {
+                    gap(start, pos - 1);
+                }
+            }
+            for &j in active.keys() {
+                if j != i {
+                    overlapping_pairs.push((i.min(j), i.max(j)));
+                }
+            }
+            *active.entry(i).or_insert(0) += 1;
+            coverage += 1;
+            max_coverage = max_coverage.max(coverage);
+        }
+        else {
+            let count = active.get_mut(&i).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                active.remove(&i);
+            }
+            coverage -= 1;
+            if coverage == 0 {
+                last_uncovered = Some(pos);
+            }
+        }
+    }
+
+    if let Some(start) = last_uncovered {
+        gap(start, universe.1 as u64);
+    }
+
+    overlapping_pairs.sort_unstable();
+    overlapping_pairs.dedup();
+
+    Sweep { max_coverage, uncovered, overlapping_pairs }
+}
+
 pub fn solve(input: &str) -> Option<(u32,u32)> {
     let (_,data) = parser::parse(input).unwrap();
 
@@ -48,3 +126,36 @@ fn test4_2() {
     let solution = solve(&include_str!("../inputs/day4.2"));
     assert_eq!(solution, Some ((500,815)));
 }
+
+#[test]
+fn test4_sweep() {
+    let (_,data) = parser::parse("1-2,4-4\n8-9,9-9\n3-4,10-12\n").unwrap();
+    let expected = Sweep {
+        max_coverage: 2,
+        uncovered: vec![(5,7)],
+        overlapping_pairs: vec![(0,2)]
+    };
+    assert_eq!(sweep(&data, (1,12)), expected);
+
+    let leading = sweep(&data, (0,15));
+    assert_eq!(leading.uncovered, vec![(0,0), (5,7), (13,15)]);
+    assert_eq!(sweep(&data, (5,6)).uncovered, vec![(5,6)]);
+    assert_eq!(sweep(&data, (9,11)).uncovered, vec![]);
+    assert_eq!(sweep(&[], (3,4)).uncovered, vec![(3,4)]);
+}
+
+#[test]
+fn test4_sweep_pairs() {
+    let (_,data) = parser::parse(include_str!("../inputs/day4.2")).unwrap();
+    let pair_overlaps = |(x1,x2): &(Interval,Interval), (y1,y2): &(Interval,Interval)|
+        overlaps(x1,y1) || overlaps(x1,y2) || overlaps(x2,y1) || overlaps(x2,y2);
+    let mut expected = Vec::new();
+    for (i,p1) in data.iter().enumerate() {
+        for (j,p2) in data.iter().enumerate().skip(i+1) {
+            if pair_overlaps(p1, p2) {
+                expected.push((i,j));
+            }
+        }
+    }
+    assert_eq!(sweep(&data, (1,99)).overlapping_pairs, expected);
+}
@@ -1,11 +1,144 @@
+use std::{fmt, cmp::Reverse, collections::{BinaryHeap, HashMap}, num::NonZeroUsize};
+
+#[derive (Debug, Clone, PartialEq, Eq)]
 pub struct Command {
     count: usize,
     src: usize,
-    dst: usize
+    dst: usize,
+    // Line of the input the command was read from, if any
+    line: Option<usize>
 }
 
 pub type Stacks = Vec<Vec<char>>;
 
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "move {} from {} to {}", self.count, self.src, self.dst)
+    }
+}
+
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub enum CommandError {
+    NoSuchStack(usize),
+    NotEnoughCrates { stack: usize, requested: usize, available: usize }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandError::NoSuchStack(stack) =>
+                write!(f, "stack {stack} does not exist"),
+            CommandError::NotEnoughCrates { stack, requested, available } =>
+                write!(f, "cannot move {requested} crates from stack {stack} \
+                    which only holds {available}")
+        }
+    }
+}
+
+#[derive (Debug, Clone, PartialEq, Eq)]
+pub struct ExecutionError {
+    pub index: usize,
+    // Source line of the command, when it was parsed
+    pub line: Option<usize>,
+    pub command: Command,
+    pub error: CommandError
+}
+
+impl fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {line}")?,
+            None => write!(f, "command {}", self.index + 1)?
+        }
+        write!(f, " ({}): {}", self.command, self.error)
+    }
+}
+
+impl std::error::Error for ExecutionError {}
+
+fn failed_at(index: usize, command: &Command) -> impl FnOnce(CommandError) -> ExecutionError + '_ {
+    move |error| ExecutionError { index, line: command.line, command: command.clone(), error }
+}
+
+// Cranes are only asked to move crates once the command has been checked
+// against the stacks, so they may assume both stacks exist, are distinct and
+// that the source holds at least `count` crates.
+pub trait Crane {
+    fn transfer(&self, count: usize, src: &mut Vec<char>, dst: &mut Vec<char>);
+
+    // Puts back onto `src` the `count` crates a transfer left on top of `dst`
+    fn untransfer(&self, count: usize, src: &mut Vec<char>, dst: &mut Vec<char>) {
+        untransfer_by_labels(self, count, src, dst)
+    }
+}
+
+// Finds out where the crane drops each lifted crate by moving labels instead,
+// which works as long as the crane only looks at `count`.
+fn untransfer_by_labels<C: Crane + ?Sized>(
+        crane: &C,
+        count: usize,
+        src: &mut Vec<char>,
+        dst: &mut Vec<char>) {
+    // Labels are numbered characters, skipping the surrogate code points
+    let label = |i: usize| char::from_u32(if i < 0xD800 {i} else {i + 0x800} as u32).unwrap();
+    let number = |c: char| if (c as usize) < 0xD800 {c as usize} else {c as usize - 0x800};
+
+    let mut labels = (0..count).map(label).collect::<Vec<char>>();
+    let mut placed = Vec::with_capacity(count);
+    crane.transfer(count, &mut labels, &mut placed);
+
+    let mut lifted = vec![' '; count];
+    for (l,c) in placed.into_iter().zip(dst.drain((dst.len()-count)..)) {
+        lifted[number(l)] = c;
+    }
+    src.extend(lifted);
+}
+
+pub struct CrateMover9000;
+
+impl Crane for CrateMover9000 {
+    fn transfer(&self, count: usize, src: &mut Vec<char>, dst: &mut Vec<char>) {
+        for _i in 0..count {
+            let c = src.pop().unwrap();
+            dst.push(c);
+        }
+    }
+
+    fn untransfer(&self, count: usize, src: &mut Vec<char>, dst: &mut Vec<char>) {
+        self.transfer(count, dst, src)
+    }
+}
+
+pub struct CrateMover9001;
+
+impl Crane for CrateMover9001 {
+    fn transfer(&self, count: usize, src: &mut Vec<char>, dst: &mut Vec<char>) {
+        let moved_crates = src.drain((src.len()-count)..);
+        dst.extend(moved_crates);
+    }
+
+    fn untransfer(&self, count: usize, src: &mut Vec<char>, dst: &mut Vec<char>) {
+        self.transfer(count, dst, src)
+    }
+}
+
+// Moves at most `capacity` crates at a time, each batch keeping its order.
+pub struct BatchCrane {
+    pub capacity: NonZeroUsize
+}
+
+impl Crane for BatchCrane {
+    fn transfer(&self, count: usize, src: &mut Vec<char>, dst: &mut Vec<char>) {
+        let capacity = self.capacity.get();
+        let mut remaining = count;
+        while remaining > 0 {
+            let n = remaining.min(capacity);
+            dst.extend(src.drain((src.len()-n)..));
+            remaining -= n;
+        }
+    }
+}
+
 mod parser  {
     use nom::{
         IResult, branch::*, multi::*,
@@ -31,52 +164,85 @@ mod parser  {
         ))(input)
     }
 
-    fn crate_array(input: &str) -> IResult<&str, Vec<Vec<char>>> {
+    fn crate_array(input: &str) -> IResult<&str, Stacks> {
         let (input, array) =
-            separated_list1(line_ending, separated_list1(space, one_crate))
+            many0(terminated(separated_list1(space, one_crate), line_ending))
                 (input)?;
         // Legend
-        let (input, _) = line_ending(input)?;
-        let (input, _) =
-            separated_list1(space, delimited(space, u32, space))
+        let (input, legend) =
+            delimited(space0, separated_list1(space1, u32), space0)
                 (input)?;
-        Ok ((input, array))
+        Ok ((input, build_stacks(array, legend.len())))
+    }
+
+    pub fn drawing(input: &str) -> IResult<&str, Stacks> {
+        all_consuming(terminated(crate_array, multispace0))(input)
     }
 
     fn command(input: &str) -> IResult<&str, Command> {
         let (input,(_,count,_,src,_,dst)) = tuple((
             tag("move "), usize, tag(" from "), usize, tag(" to "), usize
         ))(input)?;
-        Ok((input,Command { count, src, dst }))
+        Ok((input,Command { count, src, dst, line: None }))
     }
 
-    pub fn parse(input: &str) -> IResult<&str, (Vec<Vec<char>>,Vec<Command>)> {
+    pub fn parse(input: &str) -> IResult<&str, (Stacks,Vec<Command>)> {
+        let whole = input;
         let (input, crates) = crate_array(input)?;
         let (input, _) = multispace1(input)?;
-        let (input, commands) = separated_list1(line_ending, command)(input)?;
+        // Commands take one line each
+        let first = whole[..whole.len() - input.len()].matches('\n').count() + 1;
+        let (input, mut commands) = separated_list1(line_ending, command)(input)?;
+        for (line,c) in (first..).zip(&mut commands) {
+            c.line = Some(line);
+        }
         let (input, _) = all_consuming(multispace0)(input)?;
         Ok((input, (crates,commands)))
     }
 }
 
 
-fn execute_9000(command: &Command, stacks: &mut Stacks) {
-    for _i in 0..command.count {
-        let c = stacks[command.src-1].pop().unwrap();
-        stacks[command.dst-1].push(c);
+// Checks that both stacks exist and that `from` holds enough crates.
+fn check(command: &Command, stacks: &Stacks, from: usize) -> Result<(), CommandError> {
+    for &i in &[command.src, command.dst] {
+        if i == 0 || i > stacks.len() {
+            return Err(CommandError::NoSuchStack(i));
+        }
+    }
+
+    let available = stacks[from-1].len();
+    if command.count > available {
+        return Err(CommandError::NotEnoughCrates {
+            stack: from,
+            requested: command.count,
+            available
+        });
     }
+    Ok(())
 }
 
-fn execute_9001(command: &Command, stacks: &mut Stacks) {
-    let n = command.count;
-    let src = &mut stacks[command.src-1];
-    let moved_crates = src.drain((src.len()-n)..).collect::<Vec<char>>();
-    let dst = &mut stacks[command.dst-1];
-    dst.extend(moved_crates);
+pub fn execute<C: Crane + ?Sized>(crane: &C, command: &Command, stacks: &mut Stacks)
+        -> Result<(), CommandError> {
+    check(command, stacks, command.src)?;
+
+    if command.src != command.dst {
+        let mut src = std::mem::take(&mut stacks[command.src-1]);
+        crane.transfer(command.count, &mut src, &mut stacks[command.dst-1]);
+        stacks[command.src-1] = src;
+    }
+    Ok(())
 }
 
-fn build_stacks(array: Vec<Vec<char>>) -> Stacks {
-    let mut stacks: Stacks = Vec::new ();
+pub fn execute_all<C: Crane + ?Sized>(crane: &C, commands: &[Command], stacks: &mut Stacks)
+        -> Result<(), ExecutionError> {
+    for (index,command) in commands.iter().enumerate() {
+        execute(crane, command, stacks).map_err(failed_at(index, command))?;
+    }
+    Ok(())
+}
+
+pub fn build_stacks(array: Vec<Vec<char>>, count: usize) -> Stacks {
+    let mut stacks: Stacks = vec![Vec::new (); count];
 
     for line in array {
         for (i,&c) in line.iter().enumerate() {
@@ -93,24 +259,182 @@ fn build_stacks(array: Vec<Vec<char>>) -> Stacks {
     stacks
 }
 
+pub fn undo<C: Crane + ?Sized>(crane: &C, command: &Command, stacks: &mut Stacks)
+        -> Result<(), CommandError> {
+    check(command, stacks, command.dst)?;
+
+    if command.src != command.dst {
+        let mut dst = std::mem::take(&mut stacks[command.dst-1]);
+        crane.untransfer(command.count, &mut stacks[command.src-1], &mut dst);
+        stacks[command.dst-1] = dst;
+    }
+    Ok(())
+}
+
+pub fn undo_all<C: Crane + ?Sized>(crane: &C, commands: &[Command], stacks: &mut Stacks)
+        -> Result<(), ExecutionError> {
+    for (index,command) in commands.iter().enumerate().rev() {
+        undo(crane, command, stacks).map_err(failed_at(index, command))?;
+    }
+    Ok(())
+}
+
+// Lower bound on the number of commands left: every stack whose crates
+// differ from the goal above some height must be taken from or dropped onto
+// at least once, and a command does one of each.
+fn remaining_commands(stacks: &Stacks, goal: &Stacks) -> usize {
+    let mut to_take = 0;
+    let mut to_drop = 0;
+    for (v,w) in stacks.iter().zip(goal) {
+        let settled = v.iter().zip(w).take_while(|(c,d)| c == d).count();
+        if v.len() > settled {
+            to_take += 1;
+        }
+        if w.len() > settled {
+            to_drop += 1;
+        }
+    }
+    to_take.max(to_drop)
+}
+
+// A* search over arrangements, giving up after `max_expansions` states.
+pub fn infer_commands<C: Crane + ?Sized>(
+        crane: &C,
+        start: &Stacks,
+        goal: &Stacks,
+        max_expansions: usize) -> Option<Vec<Command>> {
+    let sorted_crates = |stacks: &Stacks| {
+        let mut crates = stacks.concat();
+        crates.sort_unstable();
+        crates
+    };
+    if start.len() != goal.len() || sorted_crates(start) != sorted_crates(goal) {
+        return None;
+    }
+
+    let mut states: Vec<(Stacks, Option<(usize,Command)>)> =
+        vec![(start.clone(), None)];
+    let mut costs = HashMap::from([(start.clone(), 0)]);
+    let mut queue = BinaryHeap::from([
+        Reverse((remaining_commands(start, goal), 0, 0))
+    ]);
+    let mut expansions = 0;
+
+    while let Some(Reverse((_, cost, id))) = queue.pop() {
+        let stacks = states[id].0.clone();
+
+        if &stacks == goal {
+            let mut commands = Vec::new();
+            let mut id = id;
+            while let Some((parent,command)) = &states[id].1 {
+                commands.push(command.clone());
+                id = *parent;
+            }
+            commands.reverse();
+            return Some(commands);
+        }
+
+        if costs[&stacks] < cost {
+            continue;
+        }
+        expansions += 1;
+        if expansions > max_expansions {
+            return None;
+        }
+
+        for src in 1..=stacks.len() {
+            for dst in 1..=stacks.len() {
+                if src == dst {
+                    continue;
+                }
+                for count in 1..=stacks[src-1].len() {
+                    let command = Command { count, src, dst, line: None };
+                    let mut next = stacks.clone();
+                    execute(crane, &command, &mut next).ok()?;
+                    if costs.get(&next).is_some_and(|&c| c <= cost + 1) {
+                        continue;
+                    }
+                    costs.insert(next.clone(), cost + 1);
+                    let estimate = cost + 1 + remaining_commands(&next, goal);
+                    queue.push(Reverse((estimate, cost + 1, states.len())));
+                    states.push((next, Some((id, command))));
+                }
+            }
+        }
+    }
+
+    None
+}
+
+pub fn parse_drawing(input: &str) -> Option<Stacks> {
+    let (_,stacks) = parser::drawing(input).ok()?;
+    Some(stacks)
+}
+
+pub struct Drawing<'a>(pub &'a Stacks);
+
+impl<'a> fmt::Display for Drawing<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let stacks = self.0;
+        let height = stacks.iter().map(|v| v.len()).max().unwrap_or(0);
+
+        for level in (0..height).rev() {
+            for (i,stack) in stacks.iter().enumerate() {
+                if i > 0 {
+                    write!(f, " ")?;
+                }
+                match stack.get(level) {
+                    Some(c) => write!(f, "[{c}]")?,
+                    None => write!(f, "   ")?
+                }
+            }
+            writeln!(f)?;
+        }
+
+        for i in 1..=stacks.len() {
+            if i > 1 {
+                write!(f, " ")?;
+            }
+            write!(f, "{i:^3}")?;
+        }
+        writeln!(f)
+    }
+}
+
+pub fn render_execution<C: Crane + ?Sized>(
+        crane: &C,
+        mut stacks: Stacks,
+        commands: &[Command]) -> Result<String, ExecutionError> {
+    let mut output = Drawing(&stacks).to_string();
+
+    for (index,command) in commands.iter().enumerate() {
+        execute(crane, command, &mut stacks).map_err(failed_at(index, command))?;
+        output += &format!("\n{command}\n{}", Drawing(&stacks));
+    }
+
+    Ok(output)
+}
+
+// Empty stacks have no crate to show
 fn stacks_top(stacks: Stacks) -> String {
-    stacks.iter().map(|v| v.last().unwrap()).collect::<String>()
+    stacks.iter().filter_map(|v| v.last()).collect::<String>()
+}
+
+pub fn solve_with<C: Crane + ?Sized>(input: &str, crane: &C)
+        -> Result<String, ExecutionError> {
+    let (_,(mut stacks, commands)) = parser::parse(input).unwrap();
+    execute_all(crane, &commands, &mut stacks)?;
+    Ok(stacks_top(stacks))
 }
 
 pub fn solve(input: &str) -> Option<(String,String)> {
-    let (_,(array, commands)) = parser::parse(input).unwrap();
+    let (_,(stacks, commands)) = parser::parse(input).unwrap();
 
-    let stacks = build_stacks(array);  
     let mut stacks1 = stacks.clone();
-
-    for command in &commands {
-        execute_9000(command, &mut stacks1);
-    }
+    execute_all(&CrateMover9000, &commands, &mut stacks1).ok()?;
 
     let mut stacks2 = stacks;
-    for command in &commands {
-        execute_9001(command, &mut stacks2);
-    }
+    execute_all(&CrateMover9001, &commands, &mut stacks2).ok()?;
 
     Some ((stacks_top(stacks1), stacks_top(stacks2)))
 }
@@ -126,3 +450,122 @@ fn test5_2() {
     let solution = solve(&include_str!("../inputs/day5.2"));
     assert_eq!(solution, Some ((String::from("BZLVHBWQF"),String::from("TDGJQTZSL"))));
 }
+
+#[test]
+fn test5_cranes() {
+    let input = include_str!("../inputs/day5.2");
+    let solution1 = solve_with(input, &BatchCrane { capacity: NonZeroUsize::MIN });
+    let solution2 = solve_with(input, &BatchCrane { capacity: NonZeroUsize::MAX });
+    assert_eq!(solution1, Ok(String::from("BZLVHBWQF")));
+    assert_eq!(solution2, Ok(String::from("TDGJQTZSL")));
+    let pairs = BatchCrane { capacity: NonZeroUsize::new(2).unwrap() };
+    let solution3 = solve_with(include_str!("../inputs/day5.1"), &pairs);
+    assert_eq!(solution3, Ok(String::from("MCZ")));
+}
+
+#[test]
+fn test5_errors() {
+    let header = "    [D]    \n[N] [C]    \n[Z] [M] [P]\n 1   2   3 \n\n";
+    let input = format!("{header}move 1 from 2 to 1\nmove 4 from 1 to 3\n");
+    let error = solve_with(&input, &CrateMover9000).unwrap_err();
+    assert_eq!(error.index, 1);
+    assert_eq!(error.line, Some(7));
+    assert_eq!(error.error, CommandError::NotEnoughCrates {
+        stack: 1, requested: 4, available: 3 });
+    assert_eq!(error.to_string(),
+        "line 7 (move 4 from 1 to 3): cannot move 4 crates from stack 1 which only holds 3");
+
+    let command = Command { line: None, ..error.command };
+    let error = execute_all(&CrateMover9000, &[command], &mut vec![vec!['A'], vec![], vec![]]).unwrap_err();
+    assert_eq!(error.to_string(),
+        "command 1 (move 4 from 1 to 3): cannot move 4 crates from stack 1 which only holds 1");
+
+    let input = format!("{header}move 1 from 4 to 1\n");
+    let error = solve_with(&input, &CrateMover9001).unwrap_err();
+    assert_eq!(error.error, CommandError::NoSuchStack(4));
+
+    let input = format!("{header}move 1 from 1 to 2\nmove 1 from 1 to 2\n");
+    assert_eq!(solve_with(&input, &CrateMover9000), Ok(String::from("ZP")));
+}
+
+#[test]
+fn test5_drawing() {
+    let input = include_str!("../inputs/day5.2");
+    let (_,(stacks,_)) = parser::parse(input).unwrap();
+    let drawing = Drawing(&stacks).to_string();
+    assert!(input.starts_with(&drawing));
+    assert_eq!(parse_drawing(&drawing), Some(stacks));
+
+    let mut stacks: Stacks = vec![Vec::new(); 12];
+    stacks[0] = vec!['A', 'B'];
+    stacks[9] = vec!['C'];
+    let drawing = Drawing(&stacks).to_string();
+    assert_eq!(drawing, "\
+[B]                                            
+[A]                                 [C]        
+ 1   2   3   4   5   6   7   8   9  10  11  12 
+");
+    assert_eq!(parse_drawing(&drawing), Some(stacks));
+}
+
+#[test]
+fn test5_render_execution() {
+    let input = include_str!("../inputs/day5.1");
+    let (_,(stacks,commands)) = parser::parse(input).unwrap();
+    let output = render_execution(&CrateMover9000, stacks, &commands[..1]).unwrap();
+    assert_eq!(output, "    [D]    
+[N] [C]    
+[Z] [M] [P]
+ 1   2   3 
+
+move 1 from 2 to 1
+[D]        
+[N] [C]    
+[Z] [M] [P]
+ 1   2   3 
+");
+}
+
+#[test]
+fn test5_undo() {
+    let input = include_str!("../inputs/day5.2");
+    let (_,(start,commands)) = parser::parse(input).unwrap();
+
+    let pairs = BatchCrane { capacity: NonZeroUsize::new(2).unwrap() };
+    let cranes: [&dyn Crane; 3] = [&CrateMover9000, &CrateMover9001, &pairs];
+    for crane in cranes {
+        let mut stacks = start.clone();
+        execute_all(crane, &commands, &mut stacks).unwrap();
+        assert_ne!(stacks, start);
+        undo_all(crane, &commands, &mut stacks).unwrap();
+        assert_eq!(stacks, start);
+    }
+
+    // More crates than there are characters below the surrogates
+    let tall = vec![(0..60000).map(|i| (b'A' + (i % 26) as u8) as char).collect(), Vec::new()];
+    let command = Command { count: 60000, src: 1, dst: 2, line: None };
+    let mut stacks = tall.clone();
+    let crane = BatchCrane { capacity: NonZeroUsize::new(7).unwrap() };
+    execute(&crane, &command, &mut stacks).unwrap();
+    undo(&crane, &command, &mut stacks).unwrap();
+    assert_eq!(stacks, tall);
+}
+
+#[test]
+fn test5_infer_commands() {
+    let input = include_str!("../inputs/day5.1");
+    let (_,(start,commands)) = parser::parse(input).unwrap();
+
+    let mut goal = start.clone();
+    execute_all(&CrateMover9001, &commands, &mut goal).unwrap();
+    let inferred = infer_commands(&CrateMover9001, &start, &goal, 100000).unwrap();
+    assert!(inferred.len() <= commands.len());
+    let mut stacks = start.clone();
+    execute_all(&CrateMover9001, &inferred, &mut stacks).unwrap();
+    assert_eq!(stacks, goal);
+
+    assert_eq!(infer_commands(&CrateMover9000, &start, &start, 1), Some(vec![]));
+    let mut other = start.clone();
+    other[0][0] = 'X';
+    assert_eq!(infer_commands(&CrateMover9000, &start, &other, 100000), None);
+}
@@ -1,6 +1,4 @@
-use std::collections::BTreeSet;
-
-use nom::InputIter;
+use std::{collections::VecDeque, io::{self, BufReader, Read}, num::NonZeroUsize};
 
 mod parser  {
     use nom::{IResult, character::complete::*, combinator::*};
@@ -12,14 +10,74 @@ mod parser  {
     }
 }
 
-fn find_marker(data: &str, size: usize) -> Option<usize> {
-    for i in size..=data.len() {
-        let set = BTreeSet::from_iter((&data[i-size..i]).iter_elements());
-        if set.len() == size {
-            return Some (i)
+// Keeps the count of each byte value in the last `size` bytes, so that each
+// new byte is handled in constant time.
+pub struct MarkerDetector {
+    size: usize,
+    window: VecDeque<u8>,
+    counts: [usize; 256],
+    distinct: usize,
+    position: usize
+}
+
+impl MarkerDetector {
+    pub fn new(size: NonZeroUsize) -> MarkerDetector {
+        let size = size.get();
+        MarkerDetector {
+            size,
+            window: VecDeque::with_capacity(size),
+            counts: [0; 256],
+            distinct: 0,
+            position: 0
+        }
+    }
+
+    // Number of bytes pushed so far
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    // Returns whether the last `size` bytes, ending with this one, are all
+    // different.
+    pub fn push(&mut self, byte: u8) -> bool {
+        if self.window.len() == self.size {
+            let old = self.window.pop_front().unwrap();
+            self.counts[old as usize] -= 1;
+            if self.counts[old as usize] == 0 {
+                self.distinct -= 1;
+            }
         }
+
+        self.window.push_back(byte);
+        self.counts[byte as usize] += 1;
+        if self.counts[byte as usize] == 1 {
+            self.distinct += 1;
+        }
+        self.position += 1;
+
+        self.distinct == self.size
     }
-    None
+}
+
+// Iterates over the positions of every marker in the stream, ending after the
+// first read error
+pub fn markers<R: Read>(reader: R, size: NonZeroUsize)
+        -> impl Iterator<Item = io::Result<usize>> {
+    let mut detector = MarkerDetector::new(size);
+    BufReader::new(reader).bytes()
+        .scan(false, move |failed, byte| match byte {
+            _ if *failed => None,
+            Ok(byte) => Some(detector.push(byte).then(|| Ok(detector.position()))),
+            Err(e) => {
+                *failed = true;
+                Some(Some(Err(e)))
+            }
+        })
+        .flatten()
+}
+
+fn find_marker(data: &str, size: usize) -> Option<usize> {
+    markers(data.as_bytes(), NonZeroUsize::new(size)?).next()?.ok()
 }
 
 pub fn solve(input: &str) -> Option<(usize,usize)> {
@@ -62,3 +120,33 @@ fn test6_6() {
     let solution = solve(&include_str!("../inputs/day6.6"));
     assert_eq!(solution, Some ((1987,3059)));
 }
+
+#[test]
+fn test6_markers() {
+    let data = include_str!("../inputs/day6.1").trim();
+    let expected: Vec<usize> = (4..=data.len())
+        .filter(|&i| {
+            let window = &data.as_bytes()[i-4..i];
+            window.iter().enumerate().all(|(k,c)| !window[k+1..].contains(c))
+        })
+        .collect();
+    let four = NonZeroUsize::new(4).unwrap();
+    let found = markers(data.as_bytes(), four).collect::<io::Result<Vec<usize>>>().unwrap();
+    assert_eq!(found, expected);
+
+    let bytes: &[u8] = &[0xff, 0xff, 0x00, b'\n', 0xff, 0x80];
+    let found = markers(bytes, NonZeroUsize::new(3).unwrap()).collect::<io::Result<Vec<usize>>>().unwrap();
+    assert_eq!(found, vec![4, 5, 6]);
+    assert_eq!(find_marker("abcd", 0), None);
+
+    // A reader that keeps failing
+    struct Broken;
+    impl Read for Broken {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("broken"))
+        }
+    }
+    let found: Vec<io::Result<usize>> = markers(Broken, four).take(3).collect();
+    assert_eq!(found.len(), 1);
+    assert!(found[0].is_err());
+}
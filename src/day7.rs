@@ -1,8 +1,10 @@
-use std::collections::HashMap;
+use std::{collections::BTreeMap, fmt, io::{self, Write}};
+use serde_json::{json, Value};
 
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct Dir {
-    files: HashMap<String, u32>,
-    subdirs: HashMap<String, Dir>
+    files: BTreeMap<String, u32>,
+    subdirs: BTreeMap<String, Dir>
 }
 
 mod parser  {
@@ -15,6 +17,7 @@ mod parser  {
     #[derive(Clone)]
     pub enum Path {
         Root,
+        Current,
         Parent,
         Subdir(String)
     }
@@ -27,23 +30,39 @@ mod parser  {
 
     #[derive(Clone)]
     pub enum Command {
-        ChangeDirectory(Path),
+        ChangeDirectory(Vec<Path>),
         ListDirectory(Vec<DirEntry>)
     }
 
     fn name(input: &str) -> IResult<&str, String> {
         fold_many1(
-            satisfy(|c| c.is_alphanumeric() || c == '.'),
+            satisfy(|c| !c.is_whitespace() && c != '/'),
             String::new,
             |mut s, c| { s.push(c); s }
         )(input)
     }
 
+    fn relative_path(input: &str) -> IResult<&str, Vec<Path>> {
+        let component = map(name, |name| match name.as_str() {
+            "." => Path::Current,
+            ".." => Path::Parent,
+            _ => Path::Subdir(name)
+        });
+        separated_list1(char('/'), component)(input)
+    }
+
+    fn path(input: &str) -> IResult<&str, Vec<Path>> {
+        let absolute = map(
+            preceded(char('/'), opt(relative_path)),
+            |components| {
+                let mut path = vec![Path::Root];
+                path.extend(components.unwrap_or_default());
+                path
+            });
+        terminated(alt((absolute, relative_path)), opt(char('/')))(input)
+    }
+
     pub fn parse(input: &str) -> IResult<&str, Vec<Command>> {
-        let root = value(Path::Root, tag("/"));
-        let parent = value(Path::Parent, tag(".."));
-        let subdir = map(name, Path::Subdir);
-        let path = alt((root, parent, subdir));
         let dir = map(
             preceded(terminated(tag("dir"), space1), name),
             DirEntry::Dir);
@@ -52,7 +71,7 @@ mod parser  {
             |(size,name)| DirEntry::File(name,size));
         let entry = alt((dir, file));
         let cd = preceded(
-            terminated(tag("$ cd"), multispace1),
+            terminated(tag("$ cd"), space1),
             map(path, Command::ChangeDirectory));
         let ls = preceded(
             terminated(tag("$ ls"), multispace1),
@@ -62,113 +81,450 @@ mod parser  {
     }
 }
 
-fn access_path<'a>(root: &'a mut Dir, path: &Vec<String>) -> &'a mut Dir {
-    let mut d = root;
-    for name in path {
-        d = d.subdirs.get_mut(name).unwrap();
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FsError {
+    Parse(String),
+    NotADirectory(String),
+    Conflict { path: String, name: String }
+}
+
+impl fmt::Display for FsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FsError::Parse(e) => write!(f, "invalid transcript: {e}"),
+            FsError::NotADirectory(path) => write!(f, "{path} is not a directory"),
+            FsError::Conflict { path, name } =>
+                write!(f, "conflicting listings for {name} in {path}")
+        }
+    }
+}
+
+impl std::error::Error for FsError {}
+
+fn format_path(path: &[String]) -> String {
+    format!("/{}", path.join("/"))
+}
+
+fn split_path(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|s| !s.is_empty())
+}
+
+impl Dir {
+    pub fn files(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.files.iter().map(|(name,&size)| (name.as_str(), size))
+    }
+
+    pub fn subdirs(&self) -> impl Iterator<Item = (&str, &Dir)> {
+        self.subdirs.iter().map(|(name,dir)| (name.as_str(), dir))
+    }
+
+    // Total size of the files in this directory and all its subdirectories
+    pub fn size(&self) -> u64 {
+        self.files.values().map(|&size| size as u64).sum::<u64>() +
+        self.subdirs.values().map(Dir::size).sum::<u64>()
+    }
+
+    pub fn dir(&self, path: &str) -> Option<&Dir> {
+        let mut d = self;
+        for name in split_path(path) {
+            d = d.subdirs.get(name)?;
+        }
+        Some(d)
+    }
+
+    pub fn file_size(&self, path: &str) -> Option<u32> {
+        let (dir, name) = match path.rsplit_once('/') {
+            Some((dir, name)) => (self.dir(dir)?, name),
+            None => (self, path)
+        };
+        dir.files.get(name).copied()
+    }
+
+    // Files and subdirectories together, sorted by name
+    pub fn entries(&self) -> impl Iterator<Item = (&str, Entry<'_>)> {
+        self.subdirs.iter().map(|(name,d)| (name.as_str(), Entry::Dir(d)))
+            .chain(self.files.iter().map(|(name,&s)| (name.as_str(), Entry::File(s))))
+            .collect::<BTreeMap<_,_>>()
+            .into_iter()
+    }
+
+    pub fn walk(&self) -> Walk<'_> {
+        Walk { stack: vec![(String::from("/"), Entry::Dir(self))] }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Entry<'a> {
+    Dir(&'a Dir),
+    File(u32)
+}
+
+// Depth-first walk yielding every entry with its absolute path, directories
+// before their contents and siblings sorted by name.
+pub struct Walk<'a> {
+    stack: Vec<(String, Entry<'a>)>
+}
+
+impl<'a> Iterator for Walk<'a> {
+    type Item = (String, Entry<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, entry) = self.stack.pop()?;
+        if let Entry::Dir(dir) = entry {
+            let prefix = path.trim_end_matches('/');
+            let children = dir.entries().collect::<Vec<_>>();
+            for (name, child) in children.into_iter().rev() {
+                self.stack.push((format!("{prefix}/{name}"), child));
+            }
+        }
+        Some((path, entry))
     }
-    d
 }
 
-pub fn process_command(
-        root: &mut Dir,
-        current: &mut Vec<String>,
-        command: parser::Command) {
-    match command {
-        parser::Command::ChangeDirectory(path)  =>
-            match path {
-                    parser::Path::Root => { current.clear() },
-                    parser::Path::Parent => { current.pop(); },
-                    parser::Path::Subdir(name) => { current.push(name) }
+#[derive(Debug, Clone, Default)]
+pub struct FileSystem {
+    root: Dir,
+    current: Vec<String>
+}
+
+impl FileSystem {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_transcript(input: &str) -> Result<Self, FsError> {
+        let (_,commands) = parser::parse(input)
+            .map_err(|e| FsError::Parse(e.to_string()))?;
+        let mut fs = Self::new();
+        for command in commands {
+            fs.process_command(command)?;
+        }
+        Ok(fs)
+    }
+
+    pub fn root(&self) -> &Dir {
+        &self.root
+    }
+
+    pub fn current_path(&self) -> String {
+        format_path(&self.current)
+    }
+
+    // Directories we cd into are created if they were not listed before, as
+    // the transcript tells us they exist.
+    fn current_dir(&mut self) -> Result<&mut Dir, FsError> {
+        let mut d = &mut self.root;
+        for (i,name) in self.current.iter().enumerate() {
+            if d.files.contains_key(name) {
+                return Err(FsError::NotADirectory(format_path(&self.current[..=i])));
+            }
+            d = d.subdirs.entry(name.clone()).or_default();
+        }
+        Ok(d)
+    }
+
+    pub fn process_command(&mut self, command: parser::Command) -> Result<(), FsError> {
+        match command {
+            parser::Command::ChangeDirectory(path) => {
+                for component in path {
+                    match component {
+                        parser::Path::Root => self.current.clear(),
+                        parser::Path::Current => (),
+                        parser::Path::Parent => { self.current.pop(); },
+                        parser::Path::Subdir(name) => self.current.push(name)
+                    }
+                }
+                self.current_dir()?;
             },
-        parser::Command::ListDirectory(entries) => {
-            let dir = access_path(root, current);
-            assert!(dir.files.is_empty() && dir.subdirs.is_empty());
-            for entry in entries {
-                match entry {
-                    parser::DirEntry::Dir(name) => {
-                        let new_dir = Dir {
-                            files: HashMap::new(),
-                            subdirs: HashMap::new()
-                        };
-                        let _ = dir.subdirs.insert(name.clone(), new_dir);
-                    },
-                    parser::DirEntry::File(name, size) => {
-                        let _ = dir.files.insert(name.clone(), size);
+            parser::Command::ListDirectory(entries) => {
+                let path = self.current_path();
+                let dir = self.current_dir()?;
+                for entry in entries {
+                    let conflict = |name: &str| FsError::Conflict {
+                        path: path.clone(),
+                        name: name.to_string()
+                    };
+                    match entry {
+                        parser::DirEntry::Dir(name) => {
+                            if dir.files.contains_key(&name) {
+                                return Err(conflict(&name));
+                            }
+                            dir.subdirs.entry(name).or_default();
+                        },
+                        parser::DirEntry::File(name, size) => {
+                            if dir.subdirs.contains_key(&name) ||
+                                    dir.files.get(&name).is_some_and(|&s| s != size) {
+                                return Err(conflict(&name));
+                            }
+                            dir.files.insert(name, size);
+                        }
                     }
                 }
             }
         }
+        Ok(())
     }
 }
 
-pub fn print_tree(root: &Dir) {
-    fn print(current: &Dir, indent: usize) {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirSize {
+    pub path: String,
+    pub depth: usize,
+    pub size: u64
+}
+
+// Cumulative size of every directory, each listed after its contents like
+// du does, so the root comes last.
+pub fn dir_sizes(root: &Dir) -> Vec<DirSize> {
+    fn aux(current: &Dir, path: String, depth: usize, sizes: &mut Vec<DirSize>) -> u64 {
+        let mut size = current.files.values().map(|&s| s as u64).sum();
+        let prefix = path.trim_end_matches('/');
         for (name,subdir) in current.subdirs.iter() {
-            println!("{}- {} (dir)", " ".repeat(indent), name);
-            print(subdir, indent + 2);
-        }
-        for (name,size) in current.files.iter() {
-            println!("{}- {}: {}", " ".repeat(indent), name, size);
+            size += aux(subdir, format!("{prefix}/{name}"), depth + 1, sizes);
         }
+        sizes.push(DirSize { path, depth, size });
+        size
     }
-    print(root, 0)
+
+    let mut sizes = Vec::new();
+    aux(root, String::from("/"), 0, &mut sizes);
+    sizes
 }
 
-pub fn solve(input: &str) -> Option<(u32,u32)> {
-    let (_,data) = parser::parse(input).unwrap();
+pub fn human_size(size: u64) -> String {
+    const UNITS: [&str; 6] = ["B", "K", "M", "G", "T", "P"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{size}{}", UNITS[0])
+    }
+    else if value < 10.0 {
+        format!("{value:.1}{}", UNITS[unit])
+    }
+    else {
+        format!("{value:.0}{}", UNITS[unit])
+    }
+}
 
-    let mut root = Dir {
-        files: HashMap::new(),
-        subdirs: HashMap::new() 
-    };
-    let mut current = Vec::new();
+#[derive(Debug, Clone, Default)]
+pub struct DuOptions {
+    pub max_depth: Option<usize>,
+    pub human_readable: bool
+}
 
-    for command in data {
-        process_command(&mut root, &mut current, command);
+pub fn write_du<W: Write>(root: &Dir, options: &DuOptions, out: &mut W) -> io::Result<()> {
+    for d in dir_sizes(root) {
+        if options.max_depth.is_some_and(|max_depth| d.depth > max_depth) {
+            continue;
+        }
+        if options.human_readable {
+            writeln!(out, "{}\t{}", human_size(d.size), d.path)?;
+        }
+        else {
+            writeln!(out, "{}\t{}", d.size, d.path)?;
+        }
     }
+    Ok(())
+}
 
-    fn aux1(current: &Dir) -> (u32,u32) {
-        let mut total_size = 0;
-        let mut solution1 = 0;
-        for (_name,subdir) in current.subdirs.iter() {
-            let (sub_size,sub_solution1) = aux1(subdir);
-            total_size += sub_size;
-            solution1 += sub_solution1;
+// Same layout as in the puzzle statement
+pub fn write_tree<W: Write>(root: &Dir, out: &mut W) -> io::Result<()> {
+    for (path, entry) in root.walk() {
+        let depth = split_path(&path).count();
+        let name = if depth == 0 { "/" } else { &path[path.rfind('/').unwrap()+1..] };
+        let indent = " ".repeat(2 * depth);
+        match entry {
+            Entry::Dir(_) => writeln!(out, "{indent}- {name} (dir)")?,
+            Entry::File(size) => writeln!(out, "{indent}- {name} (file, size={size})")?
         }
-        for (_name,size) in current.files.iter() {
-            total_size += size;
+    }
+    Ok(())
+}
+
+pub fn print_tree(root: &Dir) {
+    write_tree(root, &mut io::stdout().lock()).unwrap()
+}
+
+pub fn to_json(root: &Dir) -> Value {
+    fn aux(name: &str, current: &Dir) -> (Value, u64) {
+        let mut size = 0;
+        let mut children = Vec::new();
+        for (child_name, entry) in current.entries() {
+            match entry {
+                Entry::Dir(subdir) => {
+                    let (child, child_size) = aux(child_name, subdir);
+                    size += child_size;
+                    children.push(child);
+                },
+                Entry::File(file_size) => {
+                    size += file_size as u64;
+                    children.push(json!({
+                        "name": child_name,
+                        "type": "file",
+                        "size": file_size
+                    }));
+                }
+            }
         }
-        if total_size <= 100000 {
-            solution1 += total_size;
+        let value = json!({
+            "name": name,
+            "type": "dir",
+            "size": size,
+            "children": children
+        });
+        (value, size)
+    }
+
+    aux("/", root).0
+}
+
+pub fn small_directories_total(root: &Dir, threshold: u64) -> u64 {
+    dir_sizes(root).iter().map(|d| d.size).filter(|&size| size <= threshold).sum()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub path: String,
+    pub freed: u64,
+    pub sufficient: bool
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CleanupPlanner {
+    pub capacity: u64,
+    pub required: u64
+}
+
+impl CleanupPlanner {
+    pub fn needed_space(&self, root: &Dir) -> u64 {
+        let free = self.capacity.saturating_sub(root.size());
+        self.required.saturating_sub(free)
+    }
+
+    pub fn smallest_directory(&self, root: &Dir) -> Option<DirSize> {
+        let needed = self.needed_space(root);
+        dir_sizes(root).into_iter()
+            .filter(|d| d.size >= needed)
+            .min_by_key(|d| d.size)
+    }
+
+    // Every directory, the ones freeing the most space first
+    pub fn rank(&self, root: &Dir) -> Vec<Candidate> {
+        let needed = self.needed_space(root);
+        let mut candidates: Vec<Candidate> = dir_sizes(root).into_iter()
+            .map(|d| Candidate {
+                sufficient: d.size >= needed,
+                path: d.path,
+                freed: d.size
+            })
+            .collect();
+        candidates.sort_by(|c1, c2|
+            c2.freed.cmp(&c1.freed).then_with(|| c1.path.cmp(&c2.path)));
+        candidates
+    }
+
+    // Non-nested directories freeing enough space while deleting as little as
+    // possible, found by branch and bound
+    pub fn minimal_set(&self, root: &Dir) -> Option<Vec<DirSize>> {
+        let needed = self.needed_space(root);
+        if needed == 0 {
+            return Some(Vec::new());
         }
-        (total_size, solution1)
+
+        let sizes = dir_sizes(root);
+        let best = sizes.iter().enumerate()
+            .filter(|(_,d)| d.size >= needed)
+            .min_by_key(|(_,d)| d.size)?;
+
+        // Children come before their parent in post-order, the root last
+        let mut children: Vec<Vec<usize>> = vec![Vec::new(); sizes.len()];
+        let mut open: Vec<usize> = Vec::new();
+        for (i,d) in sizes.iter().enumerate() {
+            while let Some(&j) = open.last() {
+                if sizes[j].depth <= d.depth {
+                    break;
+                }
+                children[i].push(j);
+                open.pop();
+            }
+            open.push(i);
+        }
+
+        let mut search = CleanupSearch {
+            sizes: &sizes,
+            children,
+            needed,
+            best: best.1.size,
+            best_plan: vec![best.0],
+            chosen: Vec::new(),
+            pending: vec![sizes.len() - 1]
+        };
+        search.branch(0, root.size());
+
+        Some(search.best_plan.iter().map(|&i| sizes[i].clone()).collect())
     }
+}
 
-    fn aux2(current: &Dir, needed_space: u32) -> (u32,u32) {
-        let mut total_size = 0;
-        let mut solution2 = 30000000;
-        for (_name,subdir) in current.subdirs.iter() {
-            let (sub_size,sub_solution2) = aux2(subdir, needed_space);
-            total_size += sub_size;
-            if sub_solution2 >= needed_space && sub_solution2 < solution2 {
-                solution2 = sub_solution2;
+struct CleanupSearch<'a> {
+    sizes: &'a [DirSize],
+    // Indices of the subdirectories, the last listed first
+    children: Vec<Vec<usize>>,
+    needed: u64,
+    best: u64,
+    best_plan: Vec<usize>,
+    chosen: Vec<usize>,
+    // Directories still to decide on, in reverse order
+    pending: Vec<usize>
+}
+
+impl CleanupSearch<'_> {
+    // Either deletes the next pending directory or decides on its
+    // subdirectories instead. Deleting every pending directory is the most
+    // that can still be freed. Directories are tried in the order they are
+    // listed, and the first plan found wins ties.
+    fn branch(&mut self, freed: u64, pending_size: u64) {
+        if freed >= self.needed {
+            if freed < self.best {
+                self.best = freed;
+                self.best_plan = self.chosen.clone();
             }
+            return;
         }
-        for (_name,size) in current.files.iter() {
-            total_size += size;
+        if freed >= self.best || freed + pending_size < self.needed || self.best == self.needed {
+            return;
         }
-        if total_size >= needed_space && total_size < solution2 {
-            solution2 = total_size;
+        let Some(dir) = self.pending.pop() else { return };
+        let size = self.sizes[dir].size;
+
+        self.chosen.push(dir);
+        self.branch(freed + size, pending_size - size);
+        self.chosen.pop();
+
+        let count = self.children[dir].len();
+        let mut children_size = 0;
+        for k in 0..count {
+            let child = self.children[dir][k];
+            children_size += self.sizes[child].size;
+            self.pending.push(child);
         }
-        (total_size, solution2)
+        self.branch(freed, pending_size - size + children_size);
+        self.pending.truncate(self.pending.len() - count);
+        self.pending.push(dir);
     }
+}
 
-    let (total_size,solution1) = aux1(&root);
-    let needed_space = total_size - 40000000;
-    let (_,solution2) = aux2(&root, needed_space);
-    
-    Some ((solution1,solution2))
+pub fn solve(input: &str) -> Option<(u32,u32)> {
+    let fs = FileSystem::from_transcript(input).ok()?;
+    let planner = CleanupPlanner { capacity: 70000000, required: 30000000 };
+
+    let solution1 = small_directories_total(fs.root(), 100000);
+    let solution2 = planner.smallest_directory(fs.root())?.size;
+
+    Some ((solution1 as u32,solution2 as u32))
 }
 
 #[test]
@@ -182,3 +538,193 @@ fn test7_2() {
     let solution = solve(&include_str!("../inputs/day7.2"));
     assert_eq!(solution, Some ((1428881,10475598)));
 }
+
+#[test]
+fn test7_filesystem() {
+    let fs = FileSystem::from_transcript(include_str!("../inputs/day7.1")).unwrap();
+    let root = fs.root();
+    assert_eq!(root.size(), 48381165);
+    assert_eq!(root.dir("/a").map(Dir::size), Some(94853));
+    assert_eq!(root.dir("/a/e").map(Dir::size), Some(584));
+    assert_eq!(root.file_size("/d/k"), Some(7214296));
+    assert_eq!(root.file_size("a/e/i"), Some(584));
+    assert_eq!(root.file_size("/a/e"), None);
+    assert_eq!(root.dir("/x"), None);
+    assert_eq!(fs.current_path(), "/d");
+
+    let paths: Vec<String> = root.walk().map(|(path,_)| path).collect();
+    assert_eq!(paths, [
+        "/", "/a", "/a/e", "/a/e/i", "/a/f", "/a/g", "/a/h.lst",
+        "/b.txt", "/c.dat", "/d", "/d/d.ext", "/d/d.log", "/d/j", "/d/k"
+    ]);
+}
+
+#[test]
+fn test7_transcripts() {
+    let transcript = "\
+$ cd /a/b
+$ ls
+12 x
+$ cd ../c/./d/
+$ ls
+3 y
+$ cd /
+$ ls
+dir a
+5 z
+$ cd a
+$ ls
+dir b
+$ cd b
+$ ls
+12 x
+dir e
+";
+    let fs = FileSystem::from_transcript(transcript).unwrap();
+    let root = fs.root();
+    assert_eq!(root.size(), 20);
+    assert_eq!(root.file_size("/a/c/d/y"), Some(3));
+    assert!(root.dir("/a/b/e").is_some());
+    assert_eq!(fs.current_path(), "/a/b");
+
+    let conflict = FileSystem::from_transcript("$ cd /\n$ ls\n1 a\n$ ls\n2 a\n");
+    assert_eq!(conflict.unwrap_err(), FsError::Conflict {
+        path: String::from("/"),
+        name: String::from("a")
+    });
+    let not_a_dir = FileSystem::from_transcript("$ ls\n1 a\n$ cd a/b\n");
+    assert_eq!(not_a_dir.unwrap_err(), FsError::NotADirectory(String::from("/a")));
+}
+
+#[test]
+fn test7_reports() {
+    let fs = FileSystem::from_transcript(include_str!("../inputs/day7.1")).unwrap();
+
+    let mut du = Vec::new();
+    write_du(fs.root(), &DuOptions::default(), &mut du).unwrap();
+    assert_eq!(String::from_utf8(du).unwrap(), "\
+584\t/a/e
+94853\t/a
+24933642\t/d
+48381165\t/
+");
+
+    let mut du = Vec::new();
+    let options = DuOptions { max_depth: Some(0), human_readable: true };
+    write_du(fs.root(), &options, &mut du).unwrap();
+    assert_eq!(String::from_utf8(du).unwrap(), "46M\t/\n");
+    assert_eq!(human_size(584), "584B");
+    assert_eq!(human_size(94853), "93K");
+    assert_eq!(human_size(8033020), "7.7M");
+
+    let mut tree = Vec::new();
+    write_tree(fs.root(), &mut tree).unwrap();
+    assert_eq!(String::from_utf8(tree).unwrap(), "\
+- / (dir)
+  - a (dir)
+    - e (dir)
+      - i (file, size=584)
+    - f (file, size=29116)
+    - g (file, size=2557)
+    - h.lst (file, size=62596)
+  - b.txt (file, size=14848514)
+  - c.dat (file, size=8504156)
+  - d (dir)
+    - d.ext (file, size=5626152)
+    - d.log (file, size=8033020)
+    - j (file, size=4060174)
+    - k (file, size=7214296)
+");
+
+    let value = to_json(fs.root());
+    assert_eq!(value["size"], 48381165);
+    assert_eq!(value["children"][0]["name"], "a");
+    assert_eq!(value["children"][0]["children"][0]["children"][0],
+        json!({ "name": "i", "type": "file", "size": 584 }));
+    let text = value.to_string();
+    assert_eq!(serde_json::from_str::<Value>(&text).unwrap(), value);
+}
+
+#[test]
+fn test7_cleanup() {
+    let transcript = "\
+$ cd /
+$ ls
+dir x
+dir y
+dir z
+$ cd x
+$ ls
+60 a
+$ cd ../y
+$ ls
+50 b
+$ cd ../z
+$ ls
+dir w
+40 c
+$ cd w
+$ ls
+60 d
+";
+    let fs = FileSystem::from_transcript(transcript).unwrap();
+    let planner = CleanupPlanner { capacity: 300, required: 195 };
+    assert_eq!(planner.needed_space(fs.root()), 105);
+    assert_eq!(planner.smallest_directory(fs.root()).unwrap().path, "/");
+
+    let plan = planner.minimal_set(fs.root()).unwrap();
+    let paths: Vec<&str> = plan.iter().map(|d| d.path.as_str()).collect();
+    assert_eq!(paths, ["/x", "/y"]);
+
+    let ranking = planner.rank(fs.root());
+    let freed: Vec<u64> = ranking.iter().map(|c| c.freed).collect();
+    assert_eq!(freed, [210, 100, 60, 60, 50]);
+    assert_eq!(ranking[0],
+        Candidate { path: String::from("/"), freed: 210, sufficient: true });
+    assert_eq!(ranking[2].path, "/x");
+    assert!(!ranking[1].sufficient);
+
+    let planner = CleanupPlanner { capacity: 1000, required: 100 };
+    assert_eq!(planner.minimal_set(fs.root()), Some(Vec::new()));
+
+    // The search does not depend on how large the files are
+    let transcript = "\
+$ cd /
+$ ls
+dir a
+dir b
+dir c
+$ cd a
+$ ls
+300000000 f
+$ cd ../b
+$ ls
+300000000 g
+$ cd ../c
+$ ls
+1 h
+";
+    let fs = FileSystem::from_transcript(transcript).unwrap();
+    let planner = CleanupPlanner { capacity: 1000000000, required: 800000000 };
+    let plan = planner.minimal_set(fs.root()).unwrap();
+    let paths: Vec<&str> = plan.iter().map(|d| d.path.as_str()).collect();
+    assert_eq!(paths, ["/a", "/b"]);
+}
+
+#[test]
+fn test7_minimal_set() {
+    let fs = FileSystem::from_transcript(include_str!("../inputs/day7.2")).unwrap();
+    let planner = CleanupPlanner { capacity: 70000000, required: 30000000 };
+    let needed = planner.needed_space(fs.root());
+    let plan = planner.minimal_set(fs.root()).unwrap();
+    let freed: u64 = plan.iter().map(|d| d.size).sum();
+    assert_eq!(needed, 8518336);
+    assert_eq!(freed, needed);
+    let contains = |a: &str, b: &str|
+        a == b || b.starts_with(&format!("{}/", a.trim_end_matches('/')));
+    for (i,d1) in plan.iter().enumerate() {
+        for d2 in &plan[i+1..] {
+            assert!(!contains(&d1.path, &d2.path) && !contains(&d2.path, &d1.path));
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use std::fmt;
+
 use array2d::Array2D;
 
 pub struct Grid<T: std::clone::Clone>(Array2D<T>);
@@ -16,6 +18,12 @@ impl<T: std::clone::Clone> std::ops::DerefMut for Grid<T> {
     }
 }
 
+impl<T: std::clone::Clone> From<Array2D<T>> for Grid<T> {
+    fn from(array: Array2D<T>) -> Self {
+        Grid(array)
+    }
+}
+
 impl<T: std::clone::Clone + std::fmt::Display> std::fmt::Display for Grid<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         for row_iter in self.rows_iter() {
@@ -48,6 +56,14 @@ mod parser  {
             |v| Array2D::from_rows(&v).unwrap());
         all_consuming(terminated(array, multispace0))(input)
     }
+
+    // Heights separated by spaces, for elevations of more than one digit
+    pub fn parse_elevations(input: &str) -> IResult<&str, Array2D<u16>> {
+        let row = preceded(space0, separated_list1(space1, u16));
+        let rows = separated_list1(line_ending, terminated(row, space0));
+        let array = map_opt(rows, |v| Array2D::from_rows(&v).ok());
+        all_consuming(terminated(array, multispace0))(input)
+    }
 }
 
 pub fn scenic_score(grid: &Grid<u8>, i: usize, j: usize) -> Option<usize> {
@@ -88,65 +104,136 @@ pub fn scenic_score(grid: &Grid<u8>, i: usize, j: usize) -> Option<usize> {
 }
 
 
-pub fn solve(input: &str) -> Option<(usize,usize)> {
-    let (_,data) = parser::parse(input).unwrap();
+pub struct Visibility {
+    pub visible: Grid<bool>,
+    // Saturates at u64::MAX
+    pub scenic: Grid<u64>
+}
 
-    let grid = Grid(data);
-    let mut markers = Grid(
-        Array2D::filled_with(false, grid.row_len(), grid.column_len())
-    );
-
-    for i in 0..grid.row_len() {
-        let mut highest = -1;
-        for j in 0..grid.column_len() {
-            let x = *grid.get(i,j)? as i32;
-            if x > highest {
-                highest = x;
-                markers.set(i, j, true).unwrap();
-            }
-        }
-        let mut highest = -1;
-        for j in (0..grid.column_len()).rev() {
-            let x = *grid.get(i,j)? as i32;
-            if x > highest {
-                highest = x;
-                markers.set(i, j, true).unwrap();
-            }
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VisibilityError {
+    // (0,0) never leaves the tree
+    ZeroDirection
+}
+
+impl fmt::Display for VisibilityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VisibilityError::ZeroDirection => write!(f, "direction (0,0) does not point anywhere")
         }
     }
+}
+
+impl std::error::Error for VisibilityError {}
 
-    for j in 0..grid.column_len() {
-        let mut highest = -1;
-        for i in 0..grid.row_len() {
-            let x = *grid.get(i,j)? as i32;
-            if x > highest {
-                highest = x;
-                markers.set(i, j, true).unwrap();
+// Looks from each tree of the line back towards the line start. The stack
+// holds the trees not yet hidden behind a taller or equal one, so their
+// heights decrease and each tree is pushed and popped at most once.
+fn sweep_line<T: Ord + Clone>(
+        grid: &Grid<T>,
+        line: &[(usize,usize)],
+        visibility: &mut Visibility) {
+    let mut stack: Vec<(usize,&T)> = Vec::new();
+
+    for (k,&p) in line.iter().enumerate() {
+        let h = &grid[p];
+        while let Some(&(_,top)) = stack.last() {
+            if top >= h {
+                break;
             }
+            stack.pop();
         }
-        let mut highest = -1;
-        for i in (0..grid.row_len()).rev() {
-            let x = *grid.get(i,j)? as i32;
-            if x > highest {
-                highest = x;
-                markers.set(i, j, true).unwrap();
+        let distance = match stack.last() {
+            Some(&(blocker,_)) => k - blocker,
+            None => {
+                visibility.visible[p] = true;
+                k
             }
-        }
+        };
+        visibility.scenic[p] = visibility.scenic[p].saturating_mul(distance as u64);
+        stack.push((k,h));
     }
+}
+
+// Steps (row, column) between two consecutive trees along a line of sight
+pub type Direction = (i32,i32);
 
-    let solution1 = markers.elements_row_major_iter().filter(|&&x| x).count();
-    let mut solution2 = 0;
+pub const AXES: [Direction; 4] = [(-1,0), (1,0), (0,-1), (0,1)];
+pub const DIAGONALS: [Direction; 4] = [(-1,-1), (-1,1), (1,-1), (1,1)];
 
-    for i in 0..grid.row_len() {
-        for j in 0..grid.column_len() {
-            let x = scenic_score(&grid, i, j)?;
-            if x > solution2 {
-                solution2 = x;
+// The ray of slope di/dj, only going through the trees it exactly hits
+pub fn ray(di: i32, dj: i32) -> Option<Direction> {
+    fn gcd(a: u32, b: u32) -> u32 {
+        if b == 0 { a } else { gcd(b, a % b) }
+    }
+    let g = gcd(di.unsigned_abs(), dj.unsigned_abs()) as i64;
+    (g != 0).then(|| ((di as i64 / g) as i32, (dj as i64 / g) as i32))
+}
+
+fn step(grid_size: (usize,usize), (i,j): (usize,usize), (di,dj): Direction)
+        -> Option<(usize,usize)> {
+    let i = usize::try_from(i as i64 + di as i64).ok()?;
+    let j = usize::try_from(j as i64 + dj as i64).ok()?;
+    (i < grid_size.0 && j < grid_size.1).then_some((i,j))
+}
+
+// Directions are reduced to rays first, so (2,4) looks at the same trees as
+// (1,2).
+pub fn compute_visibility_along<T: Ord + Clone>(
+        grid: &Grid<T>,
+        directions: &[Direction]) -> Result<Visibility, VisibilityError> {
+    let rays = directions.iter()
+        .map(|&(di,dj)| ray(di, dj).ok_or(VisibilityError::ZeroDirection))
+        .collect::<Result<Vec<_>,_>>()?;
+    Ok(visibility_along(grid, &rays))
+}
+
+fn visibility_along<T: Ord + Clone>(grid: &Grid<T>, rays: &[Direction]) -> Visibility {
+    let size = (grid.num_rows(), grid.num_columns());
+    let mut visibility = Visibility {
+        visible: Grid(Array2D::filled_with(false, size.0, size.1)),
+        scenic: Grid(Array2D::filled_with(1, size.0, size.1))
+    };
+
+    for &d in rays {
+        let back = (-d.0, -d.1);
+        // Each line starts from the last tree before leaving the grid in
+        // direction d, and the sweep looks back towards it.
+        for i in 0..size.0 {
+            for j in 0..size.1 {
+                if step(size, (i,j), d).is_some() {
+                    continue;
+                }
+                let line: Vec<(usize,usize)> =
+                    std::iter::successors(Some((i,j)), |&p| step(size, p, back))
+                        .collect();
+                sweep_line(grid, &line, &mut visibility);
             }
         }
     }
 
-    
+    visibility
+}
+
+pub fn compute_visibility<T: Ord + Clone>(grid: &Grid<T>) -> Visibility {
+    visibility_along(grid, &AXES)
+}
+
+pub fn parse_elevations(input: &str) -> Option<Grid<u16>> {
+    let (_,data) = parser::parse_elevations(input).ok()?;
+    Some(Grid(data))
+}
+
+pub fn solve(input: &str) -> Option<(usize,usize)> {
+    let (_,data) = parser::parse(input).unwrap();
+
+    let grid = Grid(data);
+    let visibility = compute_visibility(&grid);
+
+    let solution1 =
+        visibility.visible.elements_row_major_iter().filter(|&&x| x).count();
+    let solution2 =
+        *visibility.scenic.elements_row_major_iter().max()? as usize;
 
     Some ((solution1,solution2))
 }
@@ -163,3 +250,104 @@ fn test8_2() {
     assert_eq!(solution, Some ((1792,334880)));
 }
 
+
+#[test]
+fn test8_scores() {
+    let (_,data) = parser::parse(include_str!("../inputs/day8.2")).unwrap();
+    let grid = Grid(data);
+    let visibility = compute_visibility(&grid);
+    for ((i,j),&score) in visibility.scenic.enumerate_row_major() {
+        assert_eq!(Some(score as usize), scenic_score(&grid, i, j));
+    }
+
+    let heights = [
+        vec![3, 3, 3, 3],
+        vec![3, 1, 5, 3],
+        vec![3, 3, 3, 3]
+    ];
+    let grid = Grid(Array2D::from_rows(&heights).unwrap());
+    let visibility = compute_visibility(&grid);
+    assert_eq!(visibility.visible.as_rows(), [
+        vec![true, true, true, true],
+        vec![true, false, true, true],
+        vec![true, true, true, true]
+    ]);
+    assert_eq!(visibility.scenic.as_rows(), [
+        vec![0, 0, 0, 0],
+        vec![0, 1, 2, 0],
+        vec![0, 0, 0, 0]
+    ]);
+}
+
+#[test]
+fn test8_directions() {
+    let (_,data) = parser::parse(include_str!("../inputs/day8.2")).unwrap();
+    let grid = Grid(data);
+    let mut directions = AXES.to_vec();
+    directions.extend(DIAGONALS);
+    let visibility = compute_visibility_along(&grid, &directions).unwrap();
+
+    for ((i,j),&score) in visibility.scenic.enumerate_row_major() {
+        let h = grid[(i,j)];
+        let mut expected = 1;
+        let mut visible = false;
+        for &d in &directions {
+            let mut p = (i,j);
+            let mut distance = 0;
+            let mut blocked = false;
+            while let Some(q) = step((grid.num_rows(), grid.num_columns()), p, d) {
+                distance += 1;
+                p = q;
+                if grid[q] >= h {
+                    blocked = true;
+                    break;
+                }
+            }
+            expected *= distance;
+            visible |= !blocked;
+        }
+        assert_eq!(score, expected);
+        assert_eq!(visibility.visible[(i,j)], visible);
+    }
+}
+
+#[test]
+fn test8_elevations() {
+    let grid = parse_elevations("\
+450 300 120  90  50
+300 250 400  10  60
+ 50 100 120 700 500
+").unwrap();
+    assert_eq!(ray(2, -4), Some((1, -2)));
+    assert_eq!(ray(0, 0), None);
+    assert_eq!(ray(i32::MIN, i32::MIN), Some((-1, -1)));
+    assert_eq!(ray(i32::MIN, 0), Some((-1, 0)));
+
+    assert_eq!(compute_visibility_along(&grid, &[(1,2), (0,0)]).err(),
+        Some(VisibilityError::ZeroDirection));
+    let doubled = compute_visibility_along(&grid, &[(2,4), (-3,-6)]).unwrap();
+    let visibility = compute_visibility_along(&grid, &[(1,2), (-1,-2)]).unwrap();
+    assert_eq!(doubled.scenic.as_rows(), visibility.scenic.as_rows());
+    assert_eq!(visibility.scenic.as_rows(), [
+        vec![0, 0, 0, 0, 0],
+        vec![0, 0, 1, 0, 0],
+        vec![0, 0, 0, 0, 0]
+    ]);
+    assert_eq!(visibility.visible.as_rows(), [
+        vec![true, true, true, true, true],
+        vec![true, true, false, true, true],
+        vec![true, true, true, true, true]
+    ]);
+}
+
+#[test]
+fn test8_saturation() {
+    // 300 trees away in all eight directions, 300^8 being beyond u64
+    let mut grid = Grid(Array2D::filled_with(0u8, 601, 601));
+    grid[(300,300)] = 1;
+    let mut directions = AXES.to_vec();
+    directions.extend(DIAGONALS);
+    let visibility = compute_visibility_along(&grid, &directions).unwrap();
+    assert_eq!(visibility.scenic[(300,300)], u64::MAX);
+    assert_eq!(visibility.scenic[(300,299)], 1);
+}
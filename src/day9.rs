@@ -1,13 +1,17 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, io::{self, Write}, fs, path::Path};
 
-pub enum Direction { Left, Right, Up, Down }
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Direction {
+    Left, Right, Up, Down,
+    UpLeft, UpRight, DownLeft, DownRight
+}
 
 pub type Command = (Direction, u32);
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
-struct Point {
-    x: i32,
-    y: i32
+pub struct Point {
+    pub x: i32,
+    pub y: i32
 }
 
 impl std::fmt::Display for Point {
@@ -23,20 +27,23 @@ mod parser {
         character::complete::*,
         combinator::*,
         sequence::*,
+        branch::*,
+        bytes::complete::tag,
         multi::*};
 
     use super::*;
 
     pub fn direction(input: &str) -> IResult<&str, Direction> {
-        let (input,c) = one_of("LRUD")(input)?;
-        let d = match c {
-            'L' => Direction::Left,
-            'R' => Direction::Right,
-            'U' => Direction::Up,
-            'D' => Direction::Down,
-            _ => panic!()
-        };
-        Ok((input,d))
+        alt((
+            value(Direction::UpLeft, tag("UL")),
+            value(Direction::UpRight, tag("UR")),
+            value(Direction::DownLeft, tag("DL")),
+            value(Direction::DownRight, tag("DR")),
+            value(Direction::Left, char('L')),
+            value(Direction::Right, char('R')),
+            value(Direction::Up, char('U')),
+            value(Direction::Down, char('D'))
+        ))(input)
     }
 
     pub fn parse(input: &str) -> IResult<&str, Vec<Command>> {
@@ -48,71 +55,272 @@ mod parser {
 
 
 fn move_head(p: Point, d: &Direction) -> Point {
-    match d {
-        Direction::Left => Point {x: p.x-1, y: p.y},
-        Direction::Right => Point {x: p.x+1, y: p.y},
-        Direction::Up => Point {x: p.x, y: p.y+1},
-        Direction::Down => Point {x: p.x, y: p.y-1}
+    let (dx,dy) = match d {
+        Direction::Left => (-1,0),
+        Direction::Right => (1,0),
+        Direction::Up => (0,1),
+        Direction::Down => (0,-1),
+        Direction::UpLeft => (-1,1),
+        Direction::UpRight => (1,1),
+        Direction::DownLeft => (-1,-1),
+        Direction::DownRight => (1,-1)
+    };
+    Point {x: p.x+dx, y: p.y+dy}
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Metric { Chebyshev, Manhattan }
+
+impl Metric {
+    pub fn distance(&self, p1: &Point, p2: &Point) -> i32 {
+        let (dx,dy) = ((p1.x - p2.x).abs(), (p1.y - p2.y).abs());
+        match self {
+            Metric::Chebyshev => dx.max(dy),
+            Metric::Manhattan => dx + dy
+        }
     }
 }
 
-fn distance(p1: &Point, p2: &Point) -> i32 {
-    (p1.x - p2.x).abs().max((p1.y - p2.y).abs())
+// Where knot number `index` (the head being 0) goes once the knot before it
+// has moved
+pub trait FollowRule {
+    fn follow(&self, index: usize, leader: &Point, knot: Point) -> Point;
 }
 
-fn move_tail(head: &Point, tail: Point) -> Point {
-    if distance(head, &tail) <= 1 {
-        tail
+// A knot stays put while it is within its slack of the knot before it, and
+// otherwise moves one cell toward it, diagonally if they are not aligned. The
+// default is the puzzle rule: a slack of 1 in Chebyshev distance.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SlackRule {
+    pub metric: Metric,
+    pub slack: i32,
+    // Overrides the slack of knots 1, 2, ... when given
+    pub knot_slack: Vec<i32>
+}
+
+impl Default for SlackRule {
+    fn default() -> Self {
+        SlackRule { metric: Metric::Chebyshev, slack: 1, knot_slack: Vec::new() }
     }
-    else {
-        let x = 
-            if tail.x < head.x {
-                tail.x + 1
-            }
-            else if tail.x > head.x {
-                tail.x - 1
+}
+
+impl FollowRule for SlackRule {
+    fn follow(&self, index: usize, head: &Point, tail: Point) -> Point {
+        let slack = index.checked_sub(1)
+            .and_then(|i| self.knot_slack.get(i))
+            .copied()
+            .unwrap_or(self.slack);
+        if self.metric.distance(head, &tail) <= slack {
+            tail
+        }
+        else {
+            let x = tail.x + (head.x - tail.x).signum();
+            let y = tail.y + (head.y - tail.y).signum();
+            Point {x, y}
+        }
+    }
+}
+
+// Iterates over the positions of all the knots, head first, after each step.
+// As an Iterator it hands out a copy of the rope each time, while next_rope
+// lends it.
+pub struct Trace<'a, R: FollowRule = SlackRule> {
+    commands: std::slice::Iter<'a, Command>,
+    current: Option<(&'a Direction, u32)>,
+    rope: Vec<Point>,
+    rule: R
+}
+
+impl<'a, R: FollowRule> Trace<'a, R> {
+    pub fn next_rope(&mut self) -> Option<&[Point]> {
+        while self.current.is_none_or(|(_,n)| n == 0) {
+            let (d,n) = self.commands.next()?;
+            self.current = Some((d,*n));
+        }
+        let (d,n) = self.current.as_mut()?;
+        *n -= 1;
+
+        let mut prev = None;
+        for (i,k) in self.rope.iter_mut().enumerate() {
+            if let Some(prev_k) = prev {
+                *k = self.rule.follow(i, prev_k, *k);
             }
             else {
-                tail.x
-            };
-        let y =
-            if tail.y < head.y {
-                tail.y + 1
+                *k = move_head(*k, d);
             }
-            else if tail.y > head.y {
-                tail.y - 1
-            }
-            else {
-                tail.y
-            };
-        Point {x, y}
+            prev = Some(k)
+        }
+        Some(&self.rope)
     }
 }
 
-pub fn simulate(commands: &Vec<Command>, length: usize) -> usize {
-    let mut rope = vec![Point {x:0, y:0}; length];
-    let mut visited_cells = HashSet::<Point>::new();
+impl<'a, R: FollowRule> Iterator for Trace<'a, R> {
+    type Item = Vec<Point>;
 
-    for (d,n) in commands {
-        for _i in 0..*n {
-            let mut prev = None;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_rope().map(<[Point]>::to_vec)
+    }
+}
 
-            for k in rope.iter_mut() {
-                if let Some(prev_k) = prev {
-                    *k = move_tail(prev_k, *k);
-                }
-                else {
-                    *k = move_head(*k, d);
-                }
-                prev = Some(k)
-            }
-            visited_cells.insert(*rope.last().unwrap());
-        }
+pub fn trace_with<R: FollowRule>(commands: &[Command], length: usize, rule: R)
+        -> Trace<'_, R> {
+    Trace {
+        commands: commands.iter(),
+        current: None,
+        rope: vec![Point {x:0, y:0}; length],
+        rule
     }
+}
+
+pub fn trace(commands: &[Command], length: usize) -> Trace<'_> {
+    trace_with(commands, length, SlackRule::default())
+}
 
+pub fn simulate_with<R: FollowRule>(commands: &[Command], length: usize, rule: R) -> usize {
+    let mut trace = trace_with(commands, length, rule);
+    let mut visited_cells = HashSet::new();
+    while let Some(rope) = trace.next_rope() {
+        visited_cells.insert(*rope.last().unwrap());
+    }
     visited_cells.len()
 }
 
+pub fn simulate(commands: &[Command], length: usize) -> usize {
+    simulate_with(commands, length, SlackRule::default())
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct BoundingBox {
+    pub min: Point,
+    pub max: Point
+}
+
+impl BoundingBox {
+    pub fn around<'a>(points: impl IntoIterator<Item = &'a Point>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = *points.next()?;
+        let mut bbox = BoundingBox { min: first, max: first };
+        for p in points {
+            bbox.min = Point { x: bbox.min.x.min(p.x), y: bbox.min.y.min(p.y) };
+            bbox.max = Point { x: bbox.max.x.max(p.x), y: bbox.max.y.max(p.y) };
+        }
+        Some(bbox)
+    }
+
+    pub fn width(&self) -> usize {
+        (self.max.x - self.min.x + 1) as usize
+    }
+
+    pub fn height(&self) -> usize {
+        (self.max.y - self.min.y + 1) as usize
+    }
+
+    // Cells row by row, from the top left corner
+    fn cells(&self) -> impl Iterator<Item = impl Iterator<Item = Point>> {
+        let (min, max) = (self.min, self.max);
+        (min.y..=max.y).rev().map(move |y| (min.x..=max.x).map(move |x| Point {x, y}))
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Cell { Empty, Visited, Start, Knot(usize) }
+
+fn cell(rope: &[Point], visited: &HashSet<Point>, p: Point) -> Cell {
+    // Like in the puzzle statement, the knot with the lowest index wins
+    if let Some(k) = rope.iter().position(|&q| q == p) {
+        Cell::Knot(k)
+    }
+    else if p == (Point {x:0, y:0}) {
+        Cell::Start
+    }
+    else if visited.contains(&p) {
+        Cell::Visited
+    }
+    else {
+        Cell::Empty
+    }
+}
+
+// Draws the rope and the visited cells as in the puzzle statement, within the
+// given box or else the smallest one around everything.
+pub fn render(rope: &[Point], visited: &HashSet<Point>, bbox: Option<BoundingBox>) -> String {
+    let start = Point {x:0, y:0};
+    let bbox = bbox.unwrap_or_else(||
+        BoundingBox::around(rope.iter().chain(visited).chain([&start])).unwrap());
+    let mut output = String::new();
+
+    for row in bbox.cells() {
+        for p in row {
+            output.push(match cell(rope, visited, p) {
+                Cell::Knot(0) => 'H',
+                Cell::Knot(_) if rope.len() == 2 => 'T',
+                Cell::Knot(k) => char::from_digit(k as u32, 36).unwrap_or('*'),
+                Cell::Start => 's',
+                Cell::Visited => '#',
+                Cell::Empty => '.'
+            });
+        }
+        output.push('\n');
+    }
+    output
+}
+
+// Writes a binary PPM image, each cell being a `scale` pixels wide square
+pub fn write_ppm<W: Write>(
+        rope: &[Point],
+        visited: &HashSet<Point>,
+        bbox: BoundingBox,
+        scale: usize,
+        out: &mut W) -> io::Result<()> {
+    writeln!(out, "P6\n{} {}\n255", bbox.width() * scale, bbox.height() * scale)?;
+
+    for row in bbox.cells() {
+        let mut line = Vec::with_capacity(3 * scale * bbox.width());
+        for p in row {
+            let color: [u8; 3] = match cell(rope, visited, p) {
+                Cell::Knot(0) => [255, 64, 64],
+                Cell::Knot(k) if k + 1 == rope.len() => [64, 255, 64],
+                Cell::Knot(_) => [255, 200, 64],
+                Cell::Start => [64, 64, 255],
+                Cell::Visited => [128, 128, 128],
+                Cell::Empty => [16, 16, 16]
+            };
+            for _i in 0..scale {
+                line.extend(color);
+            }
+        }
+        for _i in 0..scale {
+            out.write_all(&line)?;
+        }
+    }
+    Ok(())
+}
+
+// Writes one PPM frame per step in `dir`, all with the same bounding box, and
+// returns the number of frames.
+pub fn write_frames(commands: &[Command], length: usize, scale: usize, dir: &Path)
+        -> io::Result<usize> {
+    let start = Point {x:0, y:0};
+    let mut bbox = BoundingBox { min: start, max: start };
+    let mut ropes = trace(commands, length);
+    while let Some(rope) = ropes.next_rope() {
+        bbox = BoundingBox::around(rope.iter().chain([&bbox.min, &bbox.max])).unwrap();
+    }
+
+    fs::create_dir_all(dir)?;
+    let mut visited = HashSet::new();
+    let mut count = 0;
+    let mut ropes = trace(commands, length);
+    while let Some(rope) = ropes.next_rope() {
+        visited.insert(*rope.last().unwrap());
+        count += 1;
+        let file = fs::File::create(dir.join(format!("frame{count:06}.ppm")))?;
+        let mut out = io::BufWriter::new(file);
+        write_ppm(rope, &visited, bbox, scale, &mut out)?;
+        out.flush()?;
+    }
+    Ok(count)
+}
 
 pub fn solve(input: &str) -> Option<(usize,usize)> {
     let (_,commands) = parser::parse(input).unwrap();
@@ -136,3 +344,61 @@ fn test9_3() {
     let solution = solve(&include_str!("../inputs/day9.3"));
     assert_eq!(solution, Some ((6181, 2386)));
 }
+
+#[test]
+fn test9_render() {
+    let (_,commands) = parser::parse(include_str!("../inputs/day9.1")).unwrap();
+    let ropes: Vec<Vec<Point>> = trace(&commands, 2).collect();
+    assert_eq!(ropes.len(), 24);
+    assert_eq!(ropes[3], [Point {x:4, y:0}, Point {x:3, y:0}]);
+    let mut lent = trace(&commands, 2);
+    for rope in &ropes {
+        assert_eq!(lent.next_rope(), Some(&rope[..]));
+    }
+    assert_eq!(lent.next_rope(), None);
+
+    let visited: HashSet<Point> = ropes.iter().map(|rope| rope[1]).collect();
+    let last = ropes.last().unwrap();
+    assert_eq!(render(last, &visited, None), "\
+..##.
+...##
+.TH##
+....#
+s###.
+");
+
+    let bbox = BoundingBox::around(&visited).unwrap();
+    assert_eq!((bbox.width(), bbox.height()), (5, 5));
+    let mut image = Vec::new();
+    write_ppm(last, &visited, bbox, 2, &mut image).unwrap();
+    let header = b"P6\n10 10\n255\n";
+    assert!(image.starts_with(header));
+    assert_eq!(image.len(), header.len() + 3 * 10 * 10);
+}
+
+#[test]
+fn test9_rules() {
+    let (_,commands) = parser::parse(include_str!("../inputs/day9.1")).unwrap();
+    let ropes: Vec<Vec<Point>> = trace_with(&commands, 2, SlackRule {
+        metric: Metric::Manhattan,
+        ..SlackRule::default()
+    }).collect();
+    // Manhattan distance does not tolerate a diagonal gap
+    assert_eq!(ropes[4], [Point {x:4, y:1}, Point {x:4, y:1}]);
+    assert_eq!(ropes[5], [Point {x:4, y:2}, Point {x:4, y:1}]);
+
+    let slack2 = SlackRule { slack: 2, ..SlackRule::default() };
+    let ropes: Vec<Vec<Point>> = trace_with(&commands, 2, slack2).collect();
+    assert_eq!(ropes[3], [Point {x:4, y:0}, Point {x:2, y:0}]);
+
+    let loose_tail = SlackRule { knot_slack: vec![1, 1, 3], ..SlackRule::default() };
+    let ropes: Vec<Vec<Point>> = trace_with(&commands, 4, loose_tail).collect();
+    assert_eq!(ropes[3], [
+        Point {x:4, y:0}, Point {x:3, y:0}, Point {x:2, y:0}, Point {x:0, y:0}
+    ]);
+
+    let (_,commands) = parser::parse("UR 3\nDL 1\nR 1\n").unwrap();
+    let ropes: Vec<Vec<Point>> = trace(&commands, 3).collect();
+    assert_eq!(ropes[2], [Point {x:3, y:3}, Point {x:2, y:2}, Point {x:1, y:1}]);
+    assert_eq!(ropes[4], [Point {x:3, y:2}, Point {x:2, y:2}, Point {x:1, y:1}]);
+}